@@ -1,41 +1,657 @@
-use crate::{serial_com, utils::check_midi_format, Args};
+use crate::config::AppConfig;
+use crate::device_printf::{
+    DeviceLevel, PrintfBuffer, PrintfEncoding, PrintfFilter, PrintfLine, PrintfParser,
+};
+use crate::file_watch::FileWatcher;
+use crate::firmware::ConvertOptions;
+use crate::firmware_info::{self, FirmwareReport};
+use crate::flash::{BootEvent, FlashError, FlashOptions, FlashProgress};
+use crate::sequence_msg::SequenceMsg;
+use crate::serial_com::{self, DeviceStatus, SendError};
+use crate::serial_settings::SerialSettings;
+use crate::srec::SrecFile;
+use crate::utils::{check_midi_format, PortInfo};
+use crate::Args;
+use crossterm::style::Stylize;
 // use serial2::SerialPort;
 use serial2_tokio::SerialPort;
 use std::fs::File;
-use std::io::Read;
-pub async fn run(args: Args) {
-    let mut port = if let Ok(port) = if let Some(port_name) = args.port_name {
-        open_serial_port(port_name)
-    } else if let Ok(port_info) = SerialPort::available_ports() {
-        open_serial_port(port_info[args.port].to_str().unwrap())
-    } else {
-        panic!("No ports");
-    } {
-        port
-    } else {
-        panic!("Could not open port");
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+// CLIの終了コードに対応するエラー
+#[derive(Debug)]
+pub enum CliError {
+    Other(String),
+    NotMidi(String),
+    PortNotFound(String),
+    DeviceRefused,
+    TransferFailed(String),
+    Timeout(String),
+    InvalidSrec(String),
+}
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(_) => 1,
+            Self::NotMidi(_) => 2,
+            Self::PortNotFound(_) => 3,
+            Self::DeviceRefused => 4,
+            Self::TransferFailed(_) => 5,
+            Self::Timeout(_) => 6,
+            Self::InvalidSrec(_) => 7,
+        }
+    }
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Other(_) => "other",
+            Self::NotMidi(_) => "not-midi",
+            Self::PortNotFound(_) => "port-not-found",
+            Self::DeviceRefused => "device-refused",
+            Self::TransferFailed(_) => "transfer-failed",
+            Self::Timeout(_) => "timeout",
+            Self::InvalidSrec(_) => "invalid-srec",
+        }
+    }
+}
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Other(msg) => write!(f, "{msg}"),
+            Self::NotMidi(path) => write!(f, "Not a midi format: {path}"),
+            Self::PortNotFound(port) => write!(f, "Serial port not found: {port}"),
+            Self::DeviceRefused => write!(f, "Communication partner is not accepting."),
+            Self::TransferFailed(msg) => write!(f, "Transfer failed: {msg}"),
+            Self::Timeout(msg) => write!(f, "{msg}"),
+            Self::InvalidSrec(msg) => write!(f, "Invalid SREC file: {msg}"),
+        }
+    }
+}
+impl From<FlashError> for CliError {
+    fn from(value: FlashError) -> Self {
+        match value {
+            FlashError::Timeout(_) => CliError::Timeout(value.to_string()),
+            e => CliError::TransferFailed(e.to_string()),
+        }
+    }
+}
+impl From<SendError> for CliError {
+    fn from(value: SendError) -> Self {
+        match value {
+            SendError::Refused => CliError::DeviceRefused,
+            SendError::Timeout(_) => CliError::Timeout(value.to_string()),
+            e => CliError::TransferFailed(e.to_string()),
+        }
+    }
+}
+
+// --json 指定時に1行1オブジェクトで出力するイベント
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum CliEvent<'a> {
+    Port {
+        index: usize,
+        #[serde(flatten)]
+        info: &'a PortInfo,
+    },
+    Stage {
+        stage: String,
+    },
+    Result {
+        success: bool,
+    },
+    Sequence {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        elapsed: Option<f64>,
+        text: String,
+        msg: &'a SequenceMsg,
+    },
+    Printf {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        elapsed: Option<f64>,
+        text: &'a str,
+        level: Option<DeviceLevel>,
+        module: Option<&'a str>,
+        message: &'a str,
+    },
+    Status {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        elapsed: Option<f64>,
+        status: &'a DeviceStatus,
+    },
+    Srec {
+        #[serde(flatten)]
+        report: &'a FirmwareReport,
+    },
+    FlashProgress {
+        #[serde(flatten)]
+        progress: &'a FlashProgress,
+    },
+    Error {
+        code: i32,
+        kind: &'static str,
+        message: String,
+    },
+}
+fn emit_json(event: &CliEvent) {
+    println!("{}", serde_json::to_string(event).unwrap());
+}
+
+pub fn list_ports(json: bool) {
+    let list = crate::utils::get_serial_port_info();
+    if json {
+        list.iter().enumerate().for_each(|(index, info)| {
+            emit_json(&CliEvent::Port { index, info });
+        });
+    } else if list.is_empty() {
+        println!("No serial port found");
+    } else {
+        println!(
+            "{:>2}  {:<20} {:<9} {:<20} {:<24} {}",
+            "#", "Port", "VID:PID", "Manufacturer", "Product", "Serial"
+        );
+        list.iter().enumerate().for_each(|(i, info)| {
+            println!(
+                "{:>2}  {:<20} {:<9} {:<20} {:<24} {}",
+                i,
+                info.name,
+                info.vid_pid().unwrap_or_else(|| "-".to_string()),
+                info.manufacturer.as_deref().unwrap_or("-"),
+                info.product.as_deref().unwrap_or("-"),
+                info.serial_number.as_deref().unwrap_or("-"),
+            );
+        });
+    }
+}
+
+// SRECファイル(または変換したファームウェア)を検証して概要を表示する
+pub fn check_srec(path: &str, args: &Args) -> Result<(), CliError> {
+    let json = args.json;
+    let result = load_firmware(path, args, &AppConfig::load());
+    match &result {
+        Ok((_, report)) => print_report(report, json),
+        Err(e) => report_error(e, json),
+    }
+    result.map(|_| ())
+}
+
+fn print_report(report: &FirmwareReport, json: bool) {
+    if json {
+        emit_json(&CliEvent::Srec { report });
+    } else {
+        println!("{}\n{}", report.summary, report.info);
+    }
+}
+
+fn report_error(e: &CliError, json: bool) {
+    if json {
+        emit_json(&CliEvent::Error {
+            code: e.exit_code(),
+            kind: e.kind(),
+            message: e.to_string(),
+        });
+    } else {
+        println!("{e}");
+    }
+}
+
+pub async fn run(args: Args) -> Result<(), CliError> {
+    let json = args.json;
+    let result = run_inner(args).await;
+    if let Err(e) = result.as_ref() {
+        report_error(e, json);
+    }
+    result
+}
+
+async fn run_inner(args: Args) -> Result<(), CliError> {
+    // GUIと同じ設定ファイルを既定値として使う
+    let config = AppConfig::load();
+    let port_name = if let Some(port_name) = args.port_name.clone() {
+        port_name
+    } else if args.auto {
+        serial_settings(&args, SerialSettings::default())
+            .validate()
+            .map_err(CliError::Other)?;
+        // 保存済みのポートごとの設定に引数の指定を重ねて調べる
+        let found = crate::detect::detect_boards(
+            None,
+            |name| serial_settings(&args, config.serial_settings_for(name)),
+            crate::detect::DEFAULT_PROBE_WINDOW,
+        )
+        .await;
+        let port_name = found
+            .into_iter()
+            .next()
+            .ok_or_else(|| CliError::PortNotFound("no MIQS board detected".to_string()))?;
+        if !args.json {
+            println!("Detected board: {port_name}");
+        }
+        port_name
+    } else if let (None, Some(last_port)) = (args.port, config.last_port.clone()) {
+        last_port
+    } else {
+        let index = args.port.unwrap_or(0);
+        let port_info = SerialPort::available_ports().unwrap_or_default();
+        port_info
+            .get(index)
+            .and_then(|p| p.to_str())
+            .ok_or_else(|| CliError::PortNotFound(format!("#{}", index)))?
+            .to_string()
+    };
+    let settings = serial_settings(&args, config.serial_settings_for(&port_name));
+    settings.validate().map_err(CliError::Other)?;
+    // ポートを開く前にSRECファイルを検証する
+    let srec = match args.boot.as_ref().or(args.flash.as_ref()) {
+        Some(path) => Some(load_firmware(path, &args, &config)?),
+        None => None,
     };
+    let mut port = open_serial_port(&port_name, &settings)?;
     serial_com::clear_buffer(&mut port);
-    if let Some(path) = args.input {
-        let mut file = File::open(path).unwrap();
+    if let Some((srec, report)) = srec {
+        let options = flash_options(&args, config.flash_options.clone());
+        print_report(&report, args.json);
+        flash(&mut port, &srec, &options, args.boot.is_some(), args.json).await?;
+    }
+    let input = if let Some(path) = args.input.as_ref() {
+        let mut file = File::open(path).map_err(|e| CliError::Other(format!("{path}: {e}")))?;
         let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
-        if check_midi_format(&buf) {
-            println!("Send File Size");
-            serial_com::send_midi_file(&mut port, &buf).await.unwrap();
+        file.read_to_end(&mut buf)
+            .map_err(|e| CliError::Other(format!("{path}: {e}")))?;
+        if !check_midi_format(&buf) {
+            return Err(CliError::NotMidi(path.clone()));
+        }
+        Some(buf)
+    } else {
+        None
+    };
+    if let Some(path) = args.watch_firmware.as_ref() {
+        return watch_firmware(&mut port, path, &args, &config, input.as_deref()).await;
+    }
+    if args.tui {
+        let printf = printf_buffer(&args, &config, settings.printf_encoding)?;
+        let filter = MonitorOption::from(&args).printf_filter;
+        return crate::tui::run(port, port_name, input, printf, filter)
+            .await
+            .map_err(|e| CliError::Other(format!("terminal: {e}")));
+    }
+    if let Some(buf) = input {
+        upload(&mut port, &buf, &args).await?;
+    } else if !args.monitor && args.flash.is_none() && args.boot.is_none() {
+        return Err(CliError::Other("No input path".to_string()));
+    }
+    if args.monitor {
+        let printf = printf_buffer(&args, &config, settings.printf_encoding)?;
+        monitor(&mut port, &MonitorOption::from(&args), printf).await;
+    }
+    Ok(())
+}
+
+// モニタ表示とダッシュボードで共通のprintfの組み立て(行は保持しない)
+fn printf_buffer(
+    args: &Args,
+    config: &AppConfig,
+    encoding: PrintfEncoding,
+) -> Result<PrintfBuffer, CliError> {
+    let parser = match args.printf_pattern.as_deref() {
+        Some(pattern) => PrintfParser::new(pattern),
+        None => config.printf_parser(),
+    }
+    .map_err(|e| CliError::Other(format!("invalid printf pattern: {e}")))?;
+    let mut printf = PrintfBuffer::new(0);
+    printf.set_encoding(encoding);
+    printf.set_parser(parser);
+    Ok(printf)
+}
+
+async fn upload(port: &mut SerialPort, buf: &[u8], args: &Args) -> Result<(), CliError> {
+    let json = args.json;
+    // 時間制限は応答待ちにだけかける(大きなファイルの転送は10秒を超える)
+    let timeout = Some(Duration::from_secs(args.timeout));
+    let result = serial_com::send_midi_file_with_progress(port, buf, timeout, |stage| {
+        if json {
+            emit_json(&CliEvent::Stage {
+                stage: stage.to_string(),
+            });
         } else {
-            println!("Not a midi format");
+            println!("{stage}");
         }
+    })
+    .await
+    .map_err(CliError::from);
+    if json {
+        emit_json(&CliEvent::Result {
+            success: result.is_ok(),
+        });
+    }
+    result
+}
+
+// boot: ローダの起動待ちから Sequencer ready までを行う
+async fn flash(
+    port: &mut SerialPort,
+    srec: &SrecFile,
+    options: &FlashOptions,
+    boot: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let result = if boot {
+        // ローダが既に待機中かは分からないので、常に起動通知を待つ
+        crate::flash::boot_flash(port, srec, options, false, |event| match event {
+            BootEvent::Stage(stage) => {
+                if json {
+                    emit_json(&CliEvent::Stage {
+                        stage: stage.to_string(),
+                    });
+                } else {
+                    println!("{stage}");
+                }
+            }
+            BootEvent::Progress(progress) => print_flash_progress(progress, json),
+            BootEvent::Message(_) => {}
+        })
+        .await
     } else {
-        println!("No input path");
+        crate::flash::flash_srec(port, srec, options, |progress| {
+            print_flash_progress(progress, json)
+        })
+        .await
+    };
+    if json {
+        emit_json(&CliEvent::Result {
+            success: result.is_ok(),
+        });
+    } else if !boot && result.is_ok() {
+        println!("{}", DeviceStatus::LoadSuccess);
+    }
+    result.map_err(CliError::from)
+}
+
+fn print_flash_progress(progress: &FlashProgress, json: bool) {
+    if json {
+        emit_json(&CliEvent::FlashProgress { progress });
+        return;
+    }
+    print!(
+        "\r{}/{} records, {}/{} bytes, ETA {:.1}s ",
+        progress.records_sent,
+        progress.total_records,
+        progress.bytes_sent,
+        progress.total_bytes,
+        progress.eta_ms.unwrap_or(0) as f64 / 1000.0
+    );
+    // 最後のレコードで改行する
+    if progress.records_sent == progress.total_records {
+        println!();
+    }
+    let _ = std::io::stdout().flush();
+}
+
+// ファームウェアの更新を待って書き込み続ける(Ctrl-Cで終了)
+async fn watch_firmware(
+    port: &mut SerialPort,
+    path: &str,
+    args: &Args,
+    config: &AppConfig,
+    midi: Option<&[u8]>,
+) -> Result<(), CliError> {
+    let options = flash_options(args, config.flash_options.clone());
+    let debounce = args.debounce.unwrap_or(config.firmware_watch.debounce_ms);
+    let mut watcher = FileWatcher::new(path, Duration::from_millis(debounce));
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        if !args.json {
+            println!("Watching {path}");
+        }
+        tokio::select!(
+            _ = &mut ctrl_c => break,
+            _ = watcher.wait_for_change() => {}
+        );
+        // 失敗しても次の更新を待つ
+        let result = async {
+            let (srec, report) = load_firmware(path, args, config)?;
+            print_report(&report, args.json);
+            // 前回の起動で届いたバイトを起動通知と誤認しないよう捨てる
+            serial_com::clear_buffer(port);
+            flash(port, &srec, &options, true, args.json).await?;
+            if let Some(buf) = midi {
+                upload(port, buf, args).await?;
+            }
+            Ok::<(), CliError>(())
+        }
+        .await;
+        if let Err(e) = result {
+            report_error(&e, args.json);
+        }
+    }
+    if !args.json {
+        println!("Interrupted.");
+    }
+    Ok(())
+}
+
+// ELF/HEX/バイナリはSRECへ変換し(--save-srec があれば保存)、識別情報を取り出す
+fn load_firmware(
+    path: &str,
+    args: &Args,
+    config: &AppConfig,
+) -> Result<(SrecFile, FirmwareReport), CliError> {
+    let options = convert_options(args, config.convert_options.clone());
+    let srec = crate::firmware::load(path, &options)
+        .map_err(|e| CliError::InvalidSrec(format!("{path}: {e}")))?;
+    if let Some(dest) = args.save_srec.as_deref() {
+        srec.save(dest)
+            .map_err(|e| CliError::Other(format!("{dest}: {e}")))?;
+    }
+    // CLIではボードの実行中のバージョンを問い合わせないので比較しない(警告は常に空)
+    let report = firmware_info::report(path, &srec, &config.version_options, None)
+        .map_err(|e| CliError::InvalidSrec(format!("{path}: {e}")))?;
+    Ok((srec, report))
+}
+
+// コマンドライン引数で指定された項目だけ上書きする
+fn convert_options(args: &Args, base: ConvertOptions) -> ConvertOptions {
+    ConvertOptions {
+        format: args.firmware_format.unwrap_or(base.format),
+        record_size: args.record_size.unwrap_or(base.record_size),
+        address_width: args.address_width.unwrap_or(base.address_width),
+        base_address: args.base_address.or(base.base_address),
+    }
+}
+
+// コマンドライン引数で指定された項目だけ上書きする
+fn flash_options(args: &Args, base: FlashOptions) -> FlashOptions {
+    FlashOptions {
+        mode: args.flash_mode.unwrap_or(base.mode),
+        line_delay_ms: args.line_delay.unwrap_or(base.line_delay_ms),
+        reset: args.reset.unwrap_or(base.reset),
+        ..base
     }
 }
-fn open_serial_port(port: impl AsRef<str>) -> Result<SerialPort, String> {
-    let baud_rate = 115200;
-    let port_setting = SerialPort::open(port.as_ref(), baud_rate);
-    if port_setting.is_err() {
-        return Err("failed to open serial port".to_string());
+
+fn open_serial_port(port: impl AsRef<str>, settings: &SerialSettings) -> Result<SerialPort, CliError> {
+    settings.open(port.as_ref()).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => CliError::PortNotFound(port.as_ref().to_string()),
+        _ => CliError::Other(e.to_string()),
+    })
+}
+
+// コマンドライン引数で指定された項目だけ上書きする
+fn serial_settings(args: &Args, base: SerialSettings) -> SerialSettings {
+    SerialSettings {
+        baud_rate: args.baud.unwrap_or(base.baud_rate),
+        char_size: args.data_bits.unwrap_or(base.char_size),
+        parity: args.parity.unwrap_or(base.parity),
+        stop_bits: args.stop_bits.unwrap_or(base.stop_bits),
+        flow_control: args.flow_control.unwrap_or(base.flow_control),
+        dtr: args.dtr.or(base.dtr),
+        rts: args.rts.or(base.rts),
+        printf_encoding: args.printf_encoding.unwrap_or(base.printf_encoding),
     }
+}
 
-    Ok(port_setting.unwrap())
+// モニタ表示の設定
+struct MonitorOption {
+    channel: Vec<u8>,
+    timestamp: bool,
+    color: bool,
+    json: bool,
+    printf_filter: PrintfFilter,
+}
+impl<'a> From<&'a Args> for MonitorOption {
+    fn from(args: &'a Args) -> Self {
+        Self {
+            channel: args.channel.clone(),
+            timestamp: args.timestamp,
+            color: args.color && !args.json,
+            json: args.json,
+            printf_filter: PrintfFilter {
+                level: args.printf_level,
+                modules: args.printf_module.clone(),
+            },
+        }
+    }
+}
+
+// シーケンサからの出力を End もしくは Ctrl-C まで表示する
+async fn monitor(port: &mut SerialPort, option: &MonitorOption, mut printf: PrintfBuffer) {
+    let start = Instant::now();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select!(
+            _ = &mut ctrl_c => {
+                if !option.json {
+                    println!("Interrupted.");
+                }
+                break;
+            }
+            v = serial_com::receive_byte(port) => {
+                let Ok(v) = v else {
+                    if !option.json {
+                        println!("Serial port closed.");
+                    }
+                    break;
+                };
+                let msg = match serial_com::receive_sequence_msg(v, port).await {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        if !option.json {
+                            println!("Serial port closed: {e}");
+                        }
+                        break;
+                    }
+                };
+                if let Some(msg) = msg {
+                    if let serial_com::Message::Printf(bytes) = &msg {
+                        // printfは行単位にまとめて表示する
+                        for line in printf.push(bytes) {
+                            print_printf(&line, option, &start);
+                        }
+                    } else if print_message(&msg, option, &start) {
+                        break;
+                    }
+                }
+            }
+        );
+    }
+    if let Some(line) = printf.flush() {
+        print_printf(&line, option, &start);
+    }
+}
+
+fn print_printf(line: &PrintfLine, option: &MonitorOption, start: &Instant) {
+    if !option.printf_filter.matches(line) {
+        return;
+    }
+    if option.json {
+        emit_json(&CliEvent::Printf {
+            elapsed: option.timestamp.then(|| start.elapsed().as_secs_f64()),
+            text: &line.text,
+            level: line.level,
+            module: line.module.as_deref(),
+            message: &line.message,
+        });
+        return;
+    }
+    let text = format!("printf> {}", line.text);
+    let text = if !option.color {
+        text
+    } else {
+        match line.level {
+            Some(DeviceLevel::Error) => text.red().to_string(),
+            Some(DeviceLevel::Warn) => text.yellow().to_string(),
+            _ => text.dark_yellow().to_string(),
+        }
+    };
+    print_line(&text, option, start);
+}
+
+// メッセージを1行表示する(End を受け取ったら true を返す)
+fn print_message(msg: &serial_com::Message, option: &MonitorOption, start: &Instant) -> bool {
+    if let serial_com::Message::Sequence(msg) = msg {
+        if !msg.is_global()
+            && !option.channel.is_empty()
+            && !option.channel.contains(&msg.get_channel())
+        {
+            return false;
+        }
+    }
+    if option.json {
+        let elapsed = option.timestamp.then(|| start.elapsed().as_secs_f64());
+        match msg {
+            serial_com::Message::Sequence(msg) => emit_json(&CliEvent::Sequence {
+                elapsed,
+                text: msg.to_string(),
+                msg,
+            }),
+            // printfは print_printf が行単位で出力する
+            serial_com::Message::Printf(_) => {}
+            serial_com::Message::Status(status) => emit_json(&CliEvent::Status { elapsed, status }),
+        }
+        return matches!(msg, serial_com::Message::Sequence(msg) if msg.is_end());
+    }
+    let (line, is_end) = match msg {
+        serial_com::Message::Sequence(msg) => {
+            let line = msg.to_string();
+            if line.is_empty() {
+                return false;
+            }
+            let line = if !option.color {
+                line
+            } else if msg.is_end() {
+                line.red().to_string()
+            } else if msg.is_global() {
+                line.magenta().to_string()
+            } else {
+                line
+            };
+            (line, msg.is_end())
+        }
+        serial_com::Message::Printf(_) => return false,
+        serial_com::Message::Status(status) => {
+            let line = format!("[{status}]");
+            if option.color {
+                (line.cyan().to_string(), false)
+            } else {
+                (line, false)
+            }
+        }
+    };
+    print_line(&line, option, start);
+    is_end
+}
+
+fn print_line(line: &str, option: &MonitorOption, start: &Instant) {
+    if option.timestamp {
+        let elapsed = format!("{:>9.3}", start.elapsed().as_secs_f64());
+        if option.color {
+            println!("{} {line}", elapsed.dark_grey());
+        } else {
+            println!("{elapsed} {line}");
+        }
+    } else {
+        println!("{line}");
+    }
 }
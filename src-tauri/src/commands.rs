@@ -1,37 +1,90 @@
 // src/commands.rs
-use crate::{serial_com, utils::check_midi_format, AppState, FileInfo, ToFrontMsg};
+use crate::config::AppConfig;
+use crate::device_printf::PrintfLine;
+use crate::serial_settings::SerialSettings;
+use crate::song_loop::{self, SongLoop};
+use crate::firmware;
+use crate::firmware_info::{self, FirmwareReport};
+use crate::firmware_watch;
+use crate::flash::{self, BootEvent, FlashError, FlashOptions};
+use crate::midi_excerpt::{self, ExcerptRange};
+use crate::playlist::{self, PlaylistOptions, PlaylistState};
+use crate::srec::SrecFile;
+use crate::status::{self, ConnectionState, ConnectionStatus};
+use crate::utils::{check_midi_format, get_serial_port_info, MidiFile, PortInfo};
+use crate::serial_com::DeviceStatus;
+use crate::{serial_com, AppState, FileInfo, ToFrontMsg};
 use std::{fs::File, io::Read};
 use tauri::State;
-#[derive(Debug, PartialEq, Clone, Copy)]
+use tokio::sync::oneshot;
+// 接続タスクからの処理結果
+pub type Reply<T> = oneshot::Sender<Result<T, String>>;
+
+// 接続タスクへの要求
+#[derive(Debug)]
 pub enum InternalCommand {
-    Open,
-    Close,
-    Send,
-    SendExec,
+    Open { port_name: String, reply: Reply<()> },
+    Close { reply: Reply<()> },
+    Send { data: Vec<u8>, reply: Reply<()> },
+    SendSrec { srec: SrecFile, options: FlashOptions, reply: Reply<()> },
+    // ローダの起動待ちから Sequencer ready までを行う書き込み
+    BootFlash { srec: SrecFile, options: FlashOptions, reply: Reply<()> },
+    // ポートが抜かれた(port_watchから通知)
+    Disconnected { port_name: String },
 }
 
 impl std::fmt::Display for InternalCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Open => write!(f, "Port Open"),
-            Self::Close => write!(f, "Port Close"),
-            Self::Send => write!(f, "Send File"),
-            Self::SendExec => write!(f, "Send Exec"),
+            Self::Open { port_name, .. } => write!(f, "Port Open ({port_name})"),
+            Self::Close { .. } => write!(f, "Port Close"),
+            Self::Send { data, .. } => write!(f, "Send File ({} bytes)", data.len()),
+            Self::SendSrec { srec, .. } => write!(f, "Send SREC ({} records)", srec.records.len()),
+            Self::BootFlash { srec, .. } => write!(f, "Boot Flash ({} records)", srec.records.len()),
+            Self::Disconnected { port_name } => write!(f, "Port Disconnected ({port_name})"),
         }
     }
 }
 
+impl InternalCommand {
+    // 処理できない状態で受け取った要求にエラーを返す
+    pub fn reject(self, reason: &str) {
+        let reply = match self {
+            Self::Open { reply, .. }
+            | Self::Close { reply }
+            | Self::Send { reply, .. }
+            | Self::SendSrec { reply, .. }
+            | Self::BootFlash { reply, .. } => reply,
+            Self::Disconnected { .. } => return,
+        };
+        let _ = reply.send(Err(reason.to_string()));
+    }
+}
+
 #[tauri::command]
-pub async fn open_file(path: String, state: State<'_, AppState>) -> Result<(), String> {
+pub async fn open_file(
+    path: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
     let mut file = File::open(&path).map_err(|e| e.to_string())?;
     let mut buf = Vec::with_capacity(file.metadata().unwrap().len() as usize);
     let _ = file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
     let is_midi = check_midi_format(&buf);
     if is_midi {
         // Set File Data
-        let mut dst = state.file_data.lock().await;
-        *dst = Some(buf);
-        Ok(())
+        // file_data のロックを解放してから config をロックする(set_midi_live_reload と同じ順)
+        *state.file_data.lock().await = Some(MidiFile {
+            path: path.clone(),
+            data: buf,
+        });
+        let mut config = state.config.lock().await;
+        config.add_recent_midi_file(&path);
+        // 編集されたら自動で送り直す
+        if config.midi_live_reload {
+            crate::midi_reload::restart(&app_handle, Some(path));
+        }
+        config.save()
     } else {
         Err(format!(
             "Invalid File Format: {path} is not Standard MIDI Format."
@@ -42,7 +95,7 @@ pub async fn open_file(path: String, state: State<'_, AppState>) -> Result<(), S
 // Maybe unused?
 #[tauri::command]
 pub fn read_file(contents: Vec<u8>, _state: State<'_, AppState>) -> Result<FileInfo, String> {
-    println!("Reading file with contents of length: {}", contents.len()); // デバッグ用ログ
+    log::debug!("Reading file with contents of length: {}", contents.len());
 
     let size = contents.len();
     let is_midi = check_midi_format(&contents);
@@ -53,93 +106,566 @@ pub fn read_file(contents: Vec<u8>, _state: State<'_, AppState>) -> Result<FileI
 #[tauri::command]
 pub async fn serialport_open(
     port_name: String,
+    settings: Option<SerialSettings>,
     state: tauri::State<'_, AppState>,
-) -> Result<(), String> {
-    let async_proc_input_tx = state.inner.lock().await;
-    async_proc_input_tx
-        .send((InternalCommand::Open, port_name))
-        .await
-        .map_err(|e| e.to_string())
+) -> Result<ConnectionStatus, String> {
+    // 指定がなければ前回このポートで使った設定を使う
+    if let Some(settings) = settings {
+        settings.validate()?;
+        let mut config = state.config.lock().await;
+        config.serial_settings.insert(port_name.clone(), settings);
+        config.save()?;
+    }
+    // 接続が完了(または失敗)するまで待つ
+    request(&state, |reply| InternalCommand::Open { port_name, reply }).await?;
+    Ok(state.status.borrow().clone())
 }
 
+// 再接続待ちの場合もCloseで中止させる
 #[tauri::command]
 pub async fn serialport_close(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let async_proc_input_tx = state.inner.lock().await;
-    async_proc_input_tx
-        .send((InternalCommand::Close, String::from("")))
-        .await
-        .map_err(|e| e.to_string())
+    request(&state, |reply| InternalCommand::Close { reply }).await
 }
 
+// アップロードが完了して演奏が始まるまで待つ
 #[tauri::command]
 pub async fn send_midi_file(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let async_proc_input_tx = state.inner.lock().await;
-    async_proc_input_tx
-        .send((InternalCommand::Send, String::from("")))
+    let data = state
+        .file_data
+        .lock()
+        .await
+        .as_ref()
+        .map(|file| file.data.clone())
+        .ok_or("MIDI file is not opened")?;
+    request(&state, |reply| InternalCommand::Send { data, reply }).await
+}
+
+// 開いたMIDIファイルの範囲を切り出して送る(読み込み済みの曲はそのまま)
+// loop_playback: 切り出した範囲を loop_count 回(None なら止めるまで)繰り返す
+#[tauri::command]
+pub async fn send_midi_excerpt(
+    range: ExcerptRange,
+    loop_playback: bool,
+    loop_count: Option<u32>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let excerpt = {
+        let file = state.file_data.lock().await;
+        let file = file.as_ref().ok_or("MIDI file is not opened")?;
+        let excerpt = midi_excerpt::excerpt(&file.data, &range)
+            .map_err(|e| format!("{}: {e}", file.path))?;
+        log::info!("excerpt {} ({range}, {} bytes)", file.path, excerpt.len());
+        excerpt
+    };
+    if loop_playback {
+        song_loop::start(&app_handle, loop_count, false, Some(excerpt.clone()))?;
+    }
+    let result = request(&state, |reply| InternalCommand::Send {
+        data: excerpt,
+        reply,
+    })
+    .await;
+    if result.is_err() && loop_playback {
+        song_loop::stop(&app_handle);
+    }
+    result
+}
+
+#[tauri::command]
+pub fn get_connection_status(state: tauri::State<'_, AppState>) -> ConnectionStatus {
+    state.status.borrow().clone()
+}
+
+// 接続タスクへ要求を送り、処理結果を待つ
+pub async fn request<T>(
+    state: &AppState,
+    command: impl FnOnce(Reply<T>) -> InternalCommand,
+) -> Result<T, String> {
+    let (reply, reply_rx) = oneshot::channel();
+    state
+        .command_tx
+        .send(command(reply))
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    reply_rx.await.map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_available_serial_ports(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<PortInfo>, String> {
+    let current = state.status.borrow().port.clone();
+    Ok(get_serial_port_info()
+        .into_iter()
+        .map(|mut info| {
+            info.in_use = current.as_deref() == Some(info.name.as_str());
+            info
+        })
+        .collect())
+}
+
+// MIQSボードが接続されたポートを探す(使用中のポートは除く)
+#[tauri::command]
+pub async fn detect_boards(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let current = state.status.borrow().port.clone();
+    let config = state.config.lock().await.clone();
+    Ok(crate::detect::detect_boards(
+        current.as_deref(),
+        |name| config.serial_settings_for(name),
+        crate::detect::DEFAULT_PROBE_WINDOW,
+    )
+    .await)
+}
+
+#[tauri::command]
+pub async fn get_config(state: tauri::State<'_, AppState>) -> Result<AppConfig, String> {
+    Ok(state.config.lock().await.clone())
+}
+
+#[tauri::command]
+pub async fn update_config(
+    config: AppConfig,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let parser = config.printf_parser()?;
+    let version_parser = config.version_options.parser()?;
+    config.save()?;
+    {
+        let mut printf = state.printf.lock().unwrap();
+        printf.set_capacity(config.printf_scrollback());
+        printf.set_parser(parser);
+    }
+    state.firmware.lock().unwrap().set_parser(version_parser);
+    *state.config.lock().await = config;
+    Ok(())
+}
+
+// 保持しているデバイスのprintf出力
+#[tauri::command]
+pub fn get_printf_log(state: tauri::State<'_, AppState>) -> Vec<PrintfLine> {
+    state.printf.lock().unwrap().lines()
+}
+
+#[tauri::command]
+pub fn clear_printf_log(state: tauri::State<'_, AppState>) {
+    state.printf.lock().unwrap().clear();
 }
 
+// ポートの通信設定(未設定なら既定値)
 #[tauri::command]
-pub fn get_available_serial_ports() -> Vec<String> {
-    crate::utils::get_serial_port_list().unwrap_or_default()
+pub async fn get_serial_settings(
+    port_name: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<SerialSettings, String> {
+    Ok(state.config.lock().await.serial_settings_for(&port_name))
+}
+
+#[tauri::command]
+pub async fn get_reconnect_policy(
+    state: tauri::State<'_, AppState>,
+) -> Result<crate::connection::ReconnectPolicy, String> {
+    Ok(state.config.lock().await.reconnect_policy.clone())
 }
 
+#[tauri::command]
+pub async fn set_reconnect_policy(
+    policy: crate::connection::ReconnectPolicy,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let mut config = state.config.lock().await;
+    config.reconnect_policy = policy;
+    config.save()
+}
+
+// SRECファイル(ELF/HEX/バイナリは変換して)を検証し、識別情報と警告を返す
+#[tauri::command]
+pub async fn inspect_srec_file(
+    path: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<FirmwareReport, String> {
+    let (_, report) = load_firmware(&state, &path).await?;
+    Ok(report)
+}
+
+// 変換したSRECをファイルに保存する
+#[tauri::command]
+pub async fn save_converted_srec(
+    path: String,
+    dest: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let options = state.config.lock().await.convert_options.clone();
+    let srec = firmware::load(&path, &options).map_err(|e| format!("{path}: {e}"))?;
+    log::info!("save converted srec: {path} -> {dest}");
+    srec.save(&dest).map_err(|e| format!("{dest}: {e}"))
+}
+
+// 書き込み前にファイルを検証し、不正なら送信しない
 #[tauri::command]
 pub async fn send_srec_file(
     state: tauri::State<'_, AppState>,
     fname: String,
+    force: bool,
+) -> Result<FirmwareReport, String> {
+    let (srec, report, options) = load_srec_for_flash(&state, &fname, force).await?;
+    // ローダから書き込み結果(load success/failed)が返るまで待つ
+    request(&state, |reply| InternalCommand::SendSrec {
+        srec,
+        options,
+        reply,
+    })
+    .await?;
+    Ok(report)
+}
+
+// ローダの起動を待って書き込み、シーケンサが起動するまで待つ(進行は boot-stage で通知)
+#[tauri::command]
+pub async fn flash_firmware(
+    state: tauri::State<'_, AppState>,
+    fname: String,
+    force: bool,
+) -> Result<FirmwareReport, String> {
+    let (srec, report, options) = load_srec_for_flash(&state, &fname, force).await?;
+    request(&state, |reply| InternalCommand::BootFlash {
+        srec,
+        options,
+        reply,
+    })
+    .await?;
+    Ok(report)
+}
+
+async fn load_firmware(
+    state: &AppState,
+    path: &str,
+) -> Result<(SrecFile, FirmwareReport), String> {
+    let (convert, version) = {
+        let config = state.config.lock().await;
+        (config.convert_options.clone(), config.version_options.clone())
+    };
+    let srec = firmware::load(path, &convert).map_err(|e| format!("{path}: {e}"))?;
+    let running = state.firmware.lock().unwrap().running().cloned();
+    let report = firmware_info::report(path, &srec, &version, running)?;
+    Ok((srec, report))
+}
+
+// バリアント違いやダウングレードは、確認を経た(force)場合だけ書き込む
+pub async fn load_srec_for_flash(
+    state: &AppState,
+    fname: &str,
+    force: bool,
+) -> Result<(SrecFile, FirmwareReport, FlashOptions), String> {
+    let (srec, report) = load_firmware(state, fname).await?;
+    if !force && !report.warnings.is_empty() {
+        return Err(report.warnings.join("\n"));
+    }
+    log::info!("send srec file: {fname}\n{}\n{}", report.summary, report.info);
+    for warning in &report.warnings {
+        log::warn!("{warning}");
+    }
+    let mut config = state.config.lock().await;
+    config.add_recent_srec_file(fname);
+    config.save()?;
+    Ok((srec, report, config.flash_options.clone()))
+}
+
+// 読み込み済みのMIDIファイルが更新されたら送り直す
+#[tauri::command]
+pub async fn set_midi_live_reload(
+    enabled: bool,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    // プレイリストで送った曲も含め、読み込み済みの曲を監視する
+    // (config を持ったまま file_data をロックしない)
+    let path = state
+        .file_data
+        .lock()
+        .await
+        .as_ref()
+        .map(|file| file.path.clone());
+    let mut config = state.config.lock().await;
+    config.midi_live_reload = enabled;
+    config.save()?;
+    crate::midi_reload::restart(&app_handle, path.filter(|_| enabled));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_playlist(state: tauri::State<'_, AppState>) -> Result<PlaylistState, String> {
+    let options = state.config.lock().await.playlist.clone();
+    Ok(state.playlist.lock().unwrap().state(options))
+}
+
+#[tauri::command]
+pub async fn playlist_add(
+    paths: Vec<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.playlist.lock().unwrap().add(paths);
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn playlist_remove(
+    index: usize,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.playlist.lock().unwrap().remove(index)?;
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn playlist_move(
+    from: usize,
+    to: usize,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
 ) -> Result<(), String> {
-    state.srec_file.lock().await.replace(fname);
-    let async_proc_input_tx = state.inner.lock().await;
-    async_proc_input_tx
-        .send((InternalCommand::SendExec, String::from("")))
+    state.playlist.lock().unwrap().move_entry(from, to)?;
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn playlist_shuffle(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    state.playlist.lock().unwrap().shuffle();
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn playlist_clear(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    playlist::stop(&app_handle);
+    state.playlist.lock().unwrap().clear();
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+// index の曲から連続再生する(結果は playlist-changed で通知する)
+#[tauri::command]
+pub fn playlist_play(index: Option<usize>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    playlist::start(&app_handle, index)
+}
+
+#[tauri::command]
+pub async fn playlist_stop(app_handle: tauri::AppHandle) -> Result<(), String> {
+    playlist::stop(&app_handle);
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_playlist_options(
+    options: PlaylistOptions,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    {
+        let mut config = state.config.lock().await;
+        config.playlist = options;
+        config.save()?;
+    }
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_loop(app_handle: tauri::AppHandle) -> SongLoop {
+    song_loop::get(&app_handle)
+}
+
+// 読み込み済みの曲を count 回(None なら止めるまで)繰り返す
+// 演奏中ならその回から数え、止まっていればすぐに送る
+#[tauri::command]
+pub async fn start_loop(
+    count: Option<u32>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let data = state
+        .file_data
+        .lock()
         .await
-        .map_err(|e| e.to_string())
+        .as_ref()
+        .map(|file| file.data.clone())
+        .ok_or("MIDI file is not opened")?;
+    let playing = state.status.borrow().state == ConnectionState::Playing;
+    song_loop::start(&app_handle, count, playing, None)?;
+    if playing {
+        return Ok(());
+    }
+    let result = request(&state, |reply| InternalCommand::Send { data, reply }).await;
+    if result.is_err() {
+        song_loop::stop(&app_handle);
+    }
+    result
+}
+
+#[tauri::command]
+pub fn stop_loop(app_handle: tauri::AppHandle) {
+    song_loop::stop(&app_handle);
+}
+
+// M3U の曲でプレイリストを置き換える
+#[tauri::command]
+pub async fn import_playlist(
+    path: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let entries = playlist::import_m3u(&path)?;
+    log::info!("import playlist: {path} ({} files)", entries.len());
+    playlist::stop(&app_handle);
+    {
+        let mut playlist = state.playlist.lock().unwrap();
+        playlist.clear();
+        playlist.add(entries);
+    }
+    playlist::notify(&app_handle, None).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn export_playlist(path: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let entries = state.playlist.lock().unwrap().entries().to_vec();
+    log::info!("export playlist: {path} ({} files)", entries.len());
+    playlist::export_m3u(&path, &entries)
 }
 
-// JSの世界からのイベント分岐(trueを返すとシリアル通信を閉じる)
-// TODO: フロントへのイベント発行の実装/関数名をいい感じに
+// ファームウェアの出力ファイルを監視し、更新されたら書き込む(既に監視中なら置き換える)
+#[tauri::command]
+pub async fn start_firmware_watch(
+    path: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    if !std::path::Path::new(&path).exists() {
+        return Err(format!("{path} not found"));
+    }
+    let options = state.config.lock().await.firmware_watch.clone();
+    let handle = tauri::async_runtime::spawn(firmware_watch::watch_firmware(
+        app_handle,
+        path.clone(),
+        options,
+    ));
+    if let Some((_, old)) = state.firmware_watch.lock().unwrap().replace((path, handle)) {
+        old.abort();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_firmware_watch(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) {
+    if let Some((path, handle)) = state.firmware_watch.lock().unwrap().take() {
+        handle.abort();
+        firmware_watch::notify(&app_handle, &path, firmware_watch::WatchStage::Stopped, None);
+    }
+}
+
+// 不具合報告用にログを1つのファイルへ書き出す
+#[tauri::command]
+pub fn export_logs(path: String) -> Result<(), String> {
+    log::info!("export logs to {path}");
+    crate::logger::export(std::path::Path::new(&path))
+}
+
+// 接続中に受け取った送信要求の処理(Open/Close/切断通知は接続タスクが扱う)
 pub async fn handle_internal_control<R: tauri::Runtime>(
     control: InternalCommand,
     port: &mut serial2_tokio::SerialPort,
     manager: &impl tauri::Manager<R>,
-) -> bool {
-    let state = manager.state::<AppState>();
+) {
     match control {
-        InternalCommand::Send => {
-            println!("start send file");
+        InternalCommand::Send { data, reply } => {
+            log::info!("start send file ({} bytes)", data.len());
             manager.emit_all("message",crate::ToFrontMsg::from("start send file"));
-            match serial_com::send_midi_file(port, state.file_data.lock().await.as_ref().unwrap())
-                .await
-            {
+            status::set_state(manager, ConnectionState::Uploading);
+            let result = match serial_com::send_midi_file(port, &data).await {
                 Ok(_) => {
-                    println!("Success: Send File Data");
+                    log::info!("Success: Send File Data");
                     manager.emit_all("message",crate::ToFrontMsg::from("Success: Send File Data"));
+                    status::set_state(manager, ConnectionState::Playing);
+                    song_loop::on_send(manager);
+                    Ok(())
                 }
                 Err(msg) => {
-                    println!("Error: {}", msg);
-                    manager.emit_all("error",crate::ToFrontMsg::from(format!("Failed to send midi file: {}",msg).as_str()));
+                    let msg = format!("Failed to send midi file: {msg}");
+                    log::error!("{msg}");
+                    status::set_error(manager, msg.clone());
+                    Err(msg)
                 }
-            }
-            false
-        }
-        InternalCommand::SendExec => {
-            let fname = if let Some(srec_fname) = state.srec_file.lock().await.as_ref() {
-                srec_fname.to_string()
-            } else {
-                "".to_string()
             };
-            if fname.is_empty() {
-                println!("file is not ...");
-                return false;
+            let _ = reply.send(result);
+        }
+        InternalCommand::SendSrec {
+            srec,
+            options,
+            reply,
+        } => {
+            status::set_state(manager, ConnectionState::Flashing);
+            let result = flash::flash_srec(port, &srec, &options, |progress| {
+                let _ = manager.emit_all("flash-progress", progress);
+            })
+            .await;
+            if result.is_ok() {
+                log::info!("{}", DeviceStatus::LoadSuccess);
             }
-            println!("{}", fname);
-            serial_com::send_raw_text_file(port, fname).await;
-            false
+            let _ = reply.send(finish_flash(manager, result));
+        }
+        InternalCommand::BootFlash {
+            srec,
+            options,
+            reply,
+        } => {
+            let loader_waiting =
+                status::get_status(manager).state == ConnectionState::LoaderWaiting;
+            status::set_state(manager, ConnectionState::Flashing);
+            let result = flash::boot_flash(port, &srec, &options, loader_waiting, |event| {
+                match event {
+                    BootEvent::Stage(stage) => {
+                        log::info!("{stage}");
+                        let _ = manager.emit_all("boot-stage", stage);
+                    }
+                    BootEvent::Progress(progress) => {
+                        let _ = manager.emit_all("flash-progress", progress);
+                    }
+                    BootEvent::Message(msg) => handle_sequence_msg(msg, manager),
+                }
+            })
+            .await;
+            let _ = reply.send(finish_flash(manager, result));
+        }
+        command => command.reject("serial port is already opened"),
+    }
+}
+// 書き込み結果に応じて接続状態を戻す
+fn finish_flash<R: tauri::Runtime>(
+    manager: &impl tauri::Manager<R>,
+    result: Result<(), FlashError>,
+) -> Result<(), String> {
+    match result {
+        Ok(_) => {
+            status::set_state(manager, ConnectionState::ConnectedIdle);
+            Ok(())
+        }
+        Err(e) => {
+            let msg = format!("Failed to flash SREC: {e}");
+            log::error!("{msg}");
+            status::set_error(manager, msg.clone());
+            Err(msg)
         }
-        InternalCommand::Close => true,
-        _ => false,
     }
 }
 // TODO: フロントへの送信を実装
@@ -151,15 +677,62 @@ pub fn handle_sequence_msg<R: tauri::Runtime>(
     match msg {
         serial_com::Message::Sequence(msg) => {
             // 演奏情報
-            println!("{}", msg);
+            log::debug!("{msg}");
+            if msg.is_end() && status::get_status(manager).state == ConnectionState::Playing {
+                status::set_state(manager, ConnectionState::ConnectedIdle);
+                // ループ再生中は同じ曲を、そうでなければプレイリストの次の曲を送る
+                if !song_loop::on_end(manager) {
+                    playlist::on_end(manager);
+                }
+            }
             manager.emit_all("sequencer-msg", msg).unwrap();
         }
         serial_com::Message::Printf(msg) => {
-            // Printfの内容
-            println!("{msg}");
+            // Printfの内容(行単位でフロントへ送る)
+            let lines = manager.state::<AppState>().printf.lock().unwrap().push(&msg);
+            for line in lines {
+                emit_printf_line(manager, line);
+            }
         }
-        serial_com::Message::Message(msg) => {
-          manager.emit_all("message",crate::ToFrontMsg::from(msg.as_str()));
+        serial_com::Message::Status(device_status) => {
+            manager.emit_all("message",crate::ToFrontMsg::from(device_status.to_string().as_str()));
+            match device_status {
+                DeviceStatus::LoaderWait => status::set_state(manager, ConnectionState::LoaderWaiting),
+                DeviceStatus::LoadSuccess | DeviceStatus::SequencerReady => {
+                    status::set_state(manager, ConnectionState::ConnectedIdle)
+                }
+                DeviceStatus::LoadFailed => status::set_error(manager, device_status.to_string()),
+            }
         }
     }
 }
+
+// 改行待ちのprintf出力を確定させる(切断時)
+pub fn flush_printf<R: tauri::Runtime>(manager: &impl tauri::Manager<R>) {
+    let line = manager.state::<AppState>().printf.lock().unwrap().flush();
+    if let Some(line) = line {
+        emit_printf_line(manager, line);
+    }
+}
+
+fn emit_printf_line<R: tauri::Runtime>(manager: &impl tauri::Manager<R>, line: PrintfLine) {
+    // ログファイルにはデバイスのレベル/モジュールで記録する
+    let target = match &line.module {
+        Some(module) => format!("device::{module}"),
+        None => "device".to_string(),
+    };
+    let level = line.level.map_or(log::Level::Info, log::Level::from);
+    log::log!(target: &target, level, "{}", line.message);
+    // 起動時のバナーから実行中のバージョンを取り出す
+    let running = manager
+        .state::<AppState>()
+        .firmware
+        .lock()
+        .unwrap()
+        .observe(&line.text);
+    if let Some(version) = running {
+        log::info!("board firmware: {version}");
+        let _ = manager.emit_all("board-firmware", version);
+    }
+    let _ = manager.emit_all("device-printf", line);
+}
@@ -0,0 +1,145 @@
+// アプリケーション設定の永続化(ユーザの設定ディレクトリにJSONで保存)
+use crate::connection::ReconnectPolicy;
+use crate::device_printf::PrintfParser;
+use crate::firmware::ConvertOptions;
+use crate::firmware_info::VersionOptions;
+use crate::firmware_watch::WatchOptions;
+use crate::flash::FlashOptions;
+use crate::playlist::PlaylistOptions;
+use crate::serial_settings::SerialSettings;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const CONFIG_DIR: &str = "miqs_music_prot";
+const CONFIG_FILE: &str = "config.json";
+// 読み込めなかった設定ファイルの退避先の拡張子
+const BACKUP_EXTENSION: &str = "json.bak";
+const MAX_RECENT_FILES: usize = 10;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WindowLayout {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AppConfig {
+    pub last_port: Option<String>,
+    // ポートごとの通信設定
+    pub serial_settings: HashMap<String, SerialSettings>,
+    pub reconnect_policy: ReconnectPolicy,
+    pub last_midi_file: Option<String>,
+    pub last_srec_file: Option<String>,
+    pub recent_midi_files: Vec<String>,
+    pub recent_srec_files: Vec<String>,
+    pub window: Option<WindowLayout>,
+    // ログのフィルタ(例: "info,serial_raw=trace")
+    pub log_filter: Option<String>,
+    // デバイスのprintf出力を保持する行数(未設定なら既定値)
+    pub printf_scrollback: Option<usize>,
+    // printfのタグを解析する正規表現(未設定なら既定のパターン)
+    pub printf_pattern: Option<String>,
+    // SRECの書き込み方法
+    pub flash_options: FlashOptions,
+    // ELF/HEX/バイナリからSRECへの変換方法
+    pub convert_options: ConvertOptions,
+    // ファームウェアのバージョンの取り出し方
+    pub version_options: VersionOptions,
+    // ファームウェアの監視モード
+    pub firmware_watch: WatchOptions,
+    // 読み込み済みのMIDIファイルが更新されたら送り直す
+    pub midi_live_reload: bool,
+    // プレイリストの繰り返しと曲間の待ち時間
+    pub playlist: PlaylistOptions,
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    tauri::api::path::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+impl AppConfig {
+    // 読み込めない場合は既定値で起動する
+    pub fn load() -> Self {
+        let (config, warning) = Self::load_checked();
+        if let Some(warning) = warning {
+            log::warn!("{warning}");
+        }
+        config
+    }
+    // ロガーの初期化前にも使えるよう警告を返す
+    // 解析できないファイルは次の保存で上書きしないよう config.json.bak へ退避する
+    pub fn load_checked() -> (Self, Option<String>) {
+        let Some(path) = config_path() else {
+            return (Self::default(), None);
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (Self::default(), None),
+            Err(e) => {
+                let warning = format!("failed to read {}: {e}", path.display());
+                return (Self::default(), Some(warning));
+            }
+        };
+        match serde_json::from_str(&text) {
+            Ok(config) => (config, None),
+            Err(e) => {
+                let backup = path.with_extension(BACKUP_EXTENSION);
+                let warning = match std::fs::rename(&path, &backup) {
+                    Ok(_) => format!(
+                        "invalid config {}: {e} (moved to {})",
+                        path.display(),
+                        backup.display()
+                    ),
+                    Err(rename_error) => format!(
+                        "invalid config {}: {e} (failed to move to {}: {rename_error})",
+                        path.display(),
+                        backup.display()
+                    ),
+                };
+                (Self::default(), Some(warning))
+            }
+        }
+    }
+    pub fn save(&self) -> Result<(), String> {
+        let path = config_path().ok_or("config directory not found")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| e.to_string())
+    }
+    pub fn serial_settings_for(&self, port_name: &str) -> SerialSettings {
+        self.serial_settings
+            .get(port_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+    pub fn printf_scrollback(&self) -> usize {
+        self.printf_scrollback
+            .unwrap_or(crate::device_printf::DEFAULT_SCROLLBACK)
+    }
+    pub fn printf_parser(&self) -> Result<PrintfParser, String> {
+        match self.printf_pattern.as_deref() {
+            Some(pattern) => PrintfParser::new(pattern),
+            None => Ok(PrintfParser::default()),
+        }
+    }
+    pub fn add_recent_midi_file(&mut self, path: &str) {
+        self.last_midi_file = Some(path.to_string());
+        push_recent(&mut self.recent_midi_files, path);
+    }
+    pub fn add_recent_srec_file(&mut self, path: &str) {
+        self.last_srec_file = Some(path.to_string());
+        push_recent(&mut self.recent_srec_files, path);
+    }
+}
+
+// 先頭に追加し、重複と上限超過分を取り除く
+fn push_recent(list: &mut Vec<String>, path: &str) {
+    list.retain(|p| p != path);
+    list.insert(0, path.to_string());
+    list.truncate(MAX_RECENT_FILES);
+}
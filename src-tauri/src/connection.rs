@@ -0,0 +1,301 @@
+// シリアルポートの接続管理(切断検出と自動再接続)
+use crate::commands::{
+    flush_printf, handle_internal_control, handle_sequence_msg, InternalCommand, Reply,
+};
+use crate::serial_settings::SerialSettings;
+use crate::status::{self, ConnectionState, ConnectionStatus};
+use crate::{serial_com, AppState, ToFrontMsg};
+use serial2_tokio::SerialPort;
+use std::time::Duration;
+use tauri::Manager;
+use tokio::sync::mpsc;
+
+// 自動再接続の設定
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    // 0なら無制限
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    // 同じシリアル番号のUSBデバイスを別のポート名でも探す
+    pub match_serial_number: bool,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 0,
+            initial_delay_ms: 500,
+            max_delay_ms: 8000,
+            match_serial_number: true,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone)]
+struct DisconnectInfo {
+    port: String,
+    reason: String,
+}
+#[derive(serde::Serialize, Clone)]
+struct ReconnectInfo {
+    port: String,
+    attempt: u32,
+    delay_ms: u64,
+}
+
+// 接続ループが終了した理由
+enum Closed {
+    // 閉じ終えたら応答する
+    ByUser(Reply<()>),
+    Lost(String),
+}
+
+// フロントからのOpenを待ち、接続中はシーケンサとの通信を行う
+pub async fn connection_task(
+    app_handle: tauri::AppHandle,
+    mut command_rx: mpsc::Receiver<InternalCommand>,
+) {
+    while let Some(command) = command_rx.recv().await {
+        let (port_name, open_reply) = match command {
+            InternalCommand::Open { port_name, reply } => (port_name, reply),
+            // 未接続なので閉じるものはない
+            InternalCommand::Close { reply } => {
+                let _ = reply.send(Ok(()));
+                continue;
+            }
+            command => {
+                command.reject("serial port is not opened");
+                continue;
+            }
+        };
+        set_port_state(&app_handle, ConnectionState::Opening, Some(port_name.clone()));
+        let settings = port_settings(&app_handle, &port_name).await;
+        let mut port = match settings.open(&port_name) {
+            Ok(port) => port,
+            Err(e) => {
+                let e = e.to_string();
+                log::error!("{e}");
+                status::set_status(
+                    &app_handle,
+                    ConnectionStatus {
+                        state: ConnectionState::Error,
+                        port: None,
+                        message: Some(e.clone()),
+                    },
+                );
+                let _ = open_reply.send(Err(e));
+                continue;
+            }
+        };
+        log::info!("Connected to {port_name} ({settings})");
+        save_last_port(&app_handle, &port_name).await;
+        app_handle
+            .state::<AppState>()
+            .printf
+            .lock()
+            .unwrap()
+            .set_encoding(settings.printf_encoding);
+        let serial_number = crate::utils::usb_serial_number(&port_name);
+        let mut port_name = port_name;
+        set_port_state(&app_handle, ConnectionState::ConnectedIdle, Some(port_name.clone()));
+        app_handle
+            .emit_all("message", ToFrontMsg::port_opened(settings.applied(&port)))
+            .unwrap();
+        serial_com::clear_buffer(&mut port);
+        let _ = open_reply.send(Ok(()));
+        let mut close_reply = None;
+        loop {
+            match serve(&mut port, &port_name, &mut command_rx, &app_handle).await {
+                Closed::ByUser(reply) => {
+                    serial_com::clear_buffer(&mut port);
+                    close_reply = Some(reply);
+                    break;
+                }
+                Closed::Lost(reason) => {
+                    log::warn!("Serial port {port_name} lost: {reason}");
+                    status::set_status(
+                        &app_handle,
+                        ConnectionStatus {
+                            state: ConnectionState::Disconnected,
+                            port: Some(port_name.clone()),
+                            message: Some(reason.clone()),
+                        },
+                    );
+                    app_handle
+                        .emit_all(
+                            "disconnected",
+                            DisconnectInfo {
+                                port: port_name.clone(),
+                                reason,
+                            },
+                        )
+                        .unwrap();
+                    let policy = app_handle
+                        .state::<AppState>()
+                        .config
+                        .lock()
+                        .await
+                        .reconnect_policy
+                        .clone();
+                    if !policy.enabled {
+                        break;
+                    }
+                    let reconnected = reconnect(
+                        &port_name,
+                        serial_number.as_deref(),
+                        &settings,
+                        &policy,
+                        &mut command_rx,
+                        &app_handle,
+                    )
+                    .await;
+                    let (new_port, new_name) = match reconnected {
+                        Ok(reconnected) => reconnected,
+                        Err(Closed::ByUser(reply)) => {
+                            close_reply = Some(reply);
+                            break;
+                        }
+                        Err(Closed::Lost(_)) => break,
+                    };
+                    port = new_port;
+                    port_name = new_name;
+                    set_port_state(&app_handle, ConnectionState::ConnectedIdle, Some(port_name.clone()));
+                    app_handle
+                        .emit_all("reconnected", port_name.clone())
+                        .unwrap();
+                }
+            }
+        }
+        flush_printf(&app_handle);
+        // 別のボードがつながる可能性があるので忘れる
+        app_handle.state::<AppState>().firmware.lock().unwrap().clear();
+        set_port_state(&app_handle, ConnectionState::Disconnected, None);
+        app_handle
+            .emit_all("message", ToFrontMsg::port_closed())
+            .unwrap();
+        if let Some(reply) = close_reply {
+            let _ = reply.send(Ok(()));
+        }
+    }
+}
+
+// 保存されている通信設定(なければ既定値)
+async fn port_settings(app_handle: &tauri::AppHandle, port_name: &str) -> SerialSettings {
+    app_handle
+        .state::<AppState>()
+        .config
+        .lock()
+        .await
+        .serial_settings_for(port_name)
+}
+
+async fn save_last_port(app_handle: &tauri::AppHandle, port_name: &str) {
+    let state = app_handle.state::<AppState>();
+    let mut config = state.config.lock().await;
+    config.last_port = Some(port_name.to_string());
+    if let Err(e) = config.save() {
+        log::warn!("Failed to save config: {e}");
+    }
+}
+
+fn set_port_state(app_handle: &tauri::AppHandle, state: ConnectionState, port_name: Option<String>) {
+    status::set_status(app_handle, ConnectionStatus::new(state, port_name));
+}
+
+async fn serve(
+    port: &mut SerialPort,
+    port_name: &str,
+    command_rx: &mut mpsc::Receiver<InternalCommand>,
+    app_handle: &tauri::AppHandle,
+) -> Closed {
+    loop {
+        tokio::select!(
+            Some(command) = command_rx.recv() => {
+                match command {
+                    InternalCommand::Close { reply } => return Closed::ByUser(reply),
+                    // 再接続前の古いポートの通知は無視する
+                    InternalCommand::Disconnected { port_name: removed } => {
+                        if removed == port_name {
+                            return Closed::Lost("serial port removed".to_string());
+                        }
+                    }
+                    // フロントからのイベント
+                    command => handle_internal_control(command, port, app_handle).await,
+                }
+            }
+            v = serial_com::receive_byte(port) => {
+                match v {
+                    // Sequencerとの独自プロトコルの通信
+                    Ok(v) => match serial_com::receive_sequence_msg(v, port).await {
+                        Ok(Some(sq_msg)) => handle_sequence_msg(sq_msg, app_handle),
+                        Ok(None) => {}
+                        // フレームの途中で失われた場合も再接続する
+                        Err(e) => return Closed::Lost(e.to_string()),
+                    },
+                    // 読み込みエラーはポートが失われたとみなす
+                    Err(e) => return Closed::Lost(e),
+                }
+            }
+        );
+    }
+}
+
+// バックオフしながら同じポート(または同じシリアル番号のデバイス)を開き直す
+async fn reconnect(
+    port_name: &str,
+    serial_number: Option<&str>,
+    settings: &SerialSettings,
+    policy: &ReconnectPolicy,
+    command_rx: &mut mpsc::Receiver<InternalCommand>,
+    app_handle: &tauri::AppHandle,
+) -> Result<(SerialPort, String), Closed> {
+    let mut delay = Duration::from_millis(policy.initial_delay_ms);
+    let max_delay = Duration::from_millis(policy.max_delay_ms);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        if policy.max_attempts != 0 && attempt > policy.max_attempts {
+            app_handle
+                .emit_all("error", ToFrontMsg::from("failed to reconnect Serial Port."))
+                .unwrap();
+            return Err(Closed::Lost("failed to reconnect".to_string()));
+        }
+        app_handle
+            .emit_all(
+                "reconnecting",
+                ReconnectInfo {
+                    port: port_name.to_string(),
+                    attempt,
+                    delay_ms: delay.as_millis() as u64,
+                },
+            )
+            .unwrap();
+        set_port_state(app_handle, ConnectionState::Opening, Some(port_name.to_string()));
+        // 待機中にCloseが来たら再接続を中止する
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select!(
+                _ = &mut sleep => break,
+                Some(command) = command_rx.recv() => {
+                    match command {
+                        InternalCommand::Close { reply } => return Err(Closed::ByUser(reply)),
+                        command => command.reject("serial port is reconnecting"),
+                    }
+                }
+            );
+        }
+        let candidate = serial_number
+            .filter(|_| policy.match_serial_number)
+            .and_then(crate::utils::find_port_by_serial_number)
+            .unwrap_or_else(|| port_name.to_string());
+        if let Ok(mut port) = settings.open(&candidate) {
+            serial_com::clear_buffer(&mut port);
+            return Ok((port, candidate));
+        }
+        delay = (delay * 2).min(max_delay);
+    }
+}
@@ -0,0 +1,53 @@
+// MIQSボードが接続されたシリアルポートの自動検出
+use crate::serial_com;
+use crate::serial_settings::SerialSettings;
+use std::time::Duration;
+
+pub const DEFAULT_PROBE_WINDOW: Duration = Duration::from_millis(1500);
+
+// ポートを開いてローダ待ち(0x0)/シーケンサ準備完了(0xE)を受信できるか調べる
+pub async fn probe_port(port_name: String, settings: SerialSettings, window: Duration) -> bool {
+    let Ok(mut port) = settings.open(&port_name) else {
+        return false;
+    };
+    let wait_status = async {
+        loop {
+            match serial_com::receive_byte(&mut port).await {
+                Ok(0x0) | Ok(0xE) => return true,
+                Ok(_) => continue,
+                Err(_) => return false,
+            }
+        }
+    };
+    tokio::time::timeout(window, wait_status)
+        .await
+        .unwrap_or(false)
+}
+
+// 候補のポートを並行に調べ、ボードが見つかったポートを返す
+// settings_for: ポートごとの通信設定(保存済みの設定で調べる)
+pub async fn detect_boards(
+    exclude: Option<&str>,
+    settings_for: impl Fn(&str) -> SerialSettings,
+    window: Duration,
+) -> Vec<String> {
+    let candidates = crate::utils::get_serial_port_list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| Some(name.as_str()) != exclude)
+        .collect::<Vec<_>>();
+    let tasks = candidates
+        .into_iter()
+        .map(|name| {
+            let task = tokio::spawn(probe_port(name.clone(), settings_for(&name), window));
+            (name, task)
+        })
+        .collect::<Vec<_>>();
+    let mut found = Vec::new();
+    for (name, task) in tasks {
+        if task.await.unwrap_or(false) {
+            found.push(name);
+        }
+    }
+    found
+}
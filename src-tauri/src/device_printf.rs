@@ -0,0 +1,238 @@
+// デバイスのprintf出力の行単位化とスクロールバック
+use std::collections::VecDeque;
+
+// printf出力の文字コード(デコードできないバイトは置換文字にする)
+#[derive(
+    serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrintfEncoding {
+    #[default]
+    Utf8,
+    ShiftJis,
+    EucJp,
+    Latin1,
+    // 16進ダンプ
+    Hex,
+}
+impl PrintfEncoding {
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+            Self::EucJp => encoding_rs::EUC_JP
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+            Self::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Self::Hex => bytes
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+// "[E] audio: message" のようなタグ付きの行
+pub const DEFAULT_PATTERN: &str =
+    r"^\[(?P<level>[A-Za-z]+)\]\s*(?:(?P<module>[\w.-]+):\s+)?(?P<message>.*)$";
+
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+impl DeviceLevel {
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag.to_ascii_uppercase().as_str() {
+            "E" | "ERR" | "ERROR" => Some(Self::Error),
+            "W" | "WARN" | "WARNING" => Some(Self::Warn),
+            "I" | "INFO" => Some(Self::Info),
+            "D" | "DBG" | "DEBUG" => Some(Self::Debug),
+            "T" | "V" | "TRACE" | "VERBOSE" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+impl From<DeviceLevel> for log::Level {
+    fn from(value: DeviceLevel) -> Self {
+        match value {
+            DeviceLevel::Error => log::Level::Error,
+            DeviceLevel::Warn => log::Level::Warn,
+            DeviceLevel::Info => log::Level::Info,
+            DeviceLevel::Debug => log::Level::Debug,
+            DeviceLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+// 行頭のタグからレベルとモジュール名を取り出す
+// (level/module/message の名前付きグループを使う)
+pub struct PrintfParser {
+    regex: regex::Regex,
+}
+impl PrintfParser {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = regex::Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Self { regex })
+    }
+    fn parse(&self, text: &str) -> (Option<DeviceLevel>, Option<String>, String) {
+        let Some(caps) = self.regex.captures(text) else {
+            return (None, None, text.to_string());
+        };
+        let level = caps
+            .name("level")
+            .and_then(|m| DeviceLevel::from_tag(m.as_str()));
+        let module = caps.name("module").map(|m| m.as_str().to_string());
+        let message = caps
+            .name("message")
+            .map_or(text, |m| m.as_str())
+            .to_string();
+        (level, module, message)
+    }
+}
+impl Default for PrintfParser {
+    fn default() -> Self {
+        Self::new(DEFAULT_PATTERN).unwrap()
+    }
+}
+
+// 表示対象のレベル(これ以下の重要度は隠す)とモジュール
+#[derive(Clone, Debug, Default)]
+pub struct PrintfFilter {
+    pub level: Option<DeviceLevel>,
+    // 空なら全て
+    pub modules: Vec<String>,
+}
+impl PrintfFilter {
+    // タグのない行は Info として扱う
+    pub fn matches(&self, line: &PrintfLine) -> bool {
+        let level = line.level.unwrap_or(DeviceLevel::Info);
+        if self.level.is_some_and(|max| level > max) {
+            return false;
+        }
+        self.modules.is_empty()
+            || line
+                .module
+                .as_ref()
+                .is_some_and(|module| self.modules.contains(module))
+    }
+}
+
+pub const DEFAULT_SCROLLBACK: usize = 1000;
+// 改行が来ないまま溜まった場合はこの長さで1行とみなす
+const MAX_PARTIAL_LEN: usize = 4096;
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PrintfLine {
+    // UNIX時間(ミリ秒)
+    pub timestamp: i64,
+    // 受信した行そのもの
+    pub text: String,
+    pub level: Option<DeviceLevel>,
+    pub module: Option<String>,
+    // タグを除いた本文
+    pub message: String,
+}
+
+pub struct PrintfBuffer {
+    // 改行待ちのバイト列(マルチバイト文字が分割されても行単位でデコードする)
+    partial: Vec<u8>,
+    lines: VecDeque<PrintfLine>,
+    capacity: usize,
+    encoding: PrintfEncoding,
+    parser: PrintfParser,
+}
+impl PrintfBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            partial: Vec::new(),
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            encoding: PrintfEncoding::default(),
+            parser: PrintfParser::default(),
+        }
+    }
+    // 受信したprintfの断片をつなげ、完成した行を返す
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<PrintfLine> {
+        self.partial.extend_from_slice(chunk);
+        let mut completed = Vec::new();
+        while let Some(pos) = self.partial.iter().position(|&b| b == b'\n') {
+            let rest = self.partial.split_off(pos + 1);
+            let mut line = std::mem::replace(&mut self.partial, rest);
+            while line.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+                line.pop();
+            }
+            completed.push(self.store(&line));
+        }
+        if self.partial.len() >= MAX_PARTIAL_LEN {
+            let line = std::mem::take(&mut self.partial);
+            completed.push(self.store(&line));
+        }
+        completed
+    }
+    // 改行待ちの断片を1行として確定する(切断時など)
+    pub fn flush(&mut self) -> Option<PrintfLine> {
+        if self.partial.is_empty() {
+            return None;
+        }
+        let line = std::mem::take(&mut self.partial);
+        Some(self.store(&line))
+    }
+    fn store(&mut self, bytes: &[u8]) -> PrintfLine {
+        let text = self.encoding.decode(bytes);
+        let (level, module, message) = self.parser.parse(&text);
+        let line = PrintfLine {
+            timestamp: chrono::Local::now().timestamp_millis(),
+            text,
+            level,
+            module,
+            message,
+        };
+        if self.capacity > 0 {
+            if self.lines.len() >= self.capacity {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.clone());
+        }
+        line
+    }
+    pub fn lines(&self) -> Vec<PrintfLine> {
+        self.lines.iter().cloned().collect()
+    }
+    pub fn clear(&mut self) {
+        self.partial.clear();
+        self.lines.clear();
+    }
+    pub fn set_encoding(&mut self, encoding: PrintfEncoding) {
+        self.encoding = encoding;
+    }
+    pub fn set_parser(&mut self, parser: PrintfParser) {
+        self.parser = parser;
+    }
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+        }
+    }
+}
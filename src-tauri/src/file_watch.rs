@@ -0,0 +1,57 @@
+// ファイルの更新を監視する(更新時刻とサイズをポーリングし、書き込みが落ち着いてから通知する)
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+// (更新時刻, サイズ) ファイルがなければ None
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+pub struct FileWatcher {
+    path: PathBuf,
+    last: Stamp,
+    // 最後に変化を検出した時刻(待ちが中断されても引き継ぐ)
+    changed_at: Option<Instant>,
+    debounce: Duration,
+}
+impl FileWatcher {
+    // 現在の状態を基準にする(監視開始時点の内容では通知しない)
+    pub fn new(path: impl Into<PathBuf>, debounce: Duration) -> Self {
+        let path = path.into();
+        let last = stamp(&path);
+        Self {
+            path,
+            last,
+            changed_at: None,
+            debounce,
+        }
+    }
+    // 変更後、debounce の間変化がなくなるまで待つ(削除中は待ち続ける)
+    // select! で中断されても検出済みの変化は失われない
+    pub async fn wait_for_change(&mut self) {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let current = stamp(&self.path);
+            if current != self.last {
+                self.last = current;
+                self.changed_at = Some(Instant::now());
+                continue;
+            }
+            if current.is_some()
+                && self
+                    .changed_at
+                    .is_some_and(|t| t.elapsed() >= self.debounce)
+            {
+                self.changed_at = None;
+                return;
+            }
+        }
+    }
+}
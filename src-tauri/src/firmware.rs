@@ -0,0 +1,526 @@
+// ファームウェア(ELF/Intel HEX/バイナリ)をS-recordへ変換して読み込む
+use crate::srec::SrecFile;
+use std::path::Path;
+
+// S0レコードに入れるファイル名の最大長
+const MAX_HEADER_LEN: usize = 64;
+
+#[derive(
+    serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirmwareFormat {
+    // 拡張子と内容から判定する
+    #[default]
+    Auto,
+    Srec,
+    Elf,
+    Hex,
+    Bin,
+}
+
+// 生成するS-recordのアドレス幅
+#[derive(
+    serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq,
+)]
+pub enum AddressWidth {
+    // アドレスが収まる最小の幅
+    #[default]
+    #[serde(rename = "auto")]
+    #[value(name = "auto")]
+    Auto,
+    #[serde(rename = "16")]
+    #[value(name = "16")]
+    Bits16,
+    #[serde(rename = "24")]
+    #[value(name = "24")]
+    Bits24,
+    #[serde(rename = "32")]
+    #[value(name = "32")]
+    Bits32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ConvertOptions {
+    pub format: FirmwareFormat,
+    // 1レコードあたりのデータバイト数
+    pub record_size: usize,
+    pub address_width: AddressWidth,
+    // バイナリを配置するアドレス(バイナリでは必須)
+    pub base_address: Option<u32>,
+}
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            format: FirmwareFormat::default(),
+            record_size: 32,
+            address_width: AddressWidth::default(),
+            base_address: None,
+        }
+    }
+}
+
+// "0x8000" のような16進表記も受け付ける
+pub fn parse_address(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("invalid address {s}: {e}"))
+}
+
+// 連続したメモリ領域の集まり
+#[derive(Debug, Default)]
+struct Image {
+    segments: Vec<(u32, Vec<u8>)>,
+    entry: Option<u32>,
+}
+impl Image {
+    // 直前の領域に続く場合はつなげる
+    fn push(&mut self, address: u32, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        match self.segments.last_mut() {
+            Some((start, bytes)) if *start as u64 + bytes.len() as u64 == address as u64 => {
+                bytes.extend_from_slice(data)
+            }
+            _ => self.segments.push((address, data.to_vec())),
+        }
+    }
+}
+
+// ファイルを読み込み、必要ならS-recordへ変換して検証する
+pub fn load(path: impl AsRef<Path>, options: &ConvertOptions) -> Result<SrecFile, String> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let format = match options.format {
+        FirmwareFormat::Auto => detect(path, &bytes)?,
+        format => format,
+    };
+    let image = match format {
+        FirmwareFormat::Srec | FirmwareFormat::Auto => {
+            let text = String::from_utf8_lossy(&bytes);
+            return SrecFile::parse(&text).map_err(|e| e.to_string());
+        }
+        FirmwareFormat::Elf => parse_elf(&bytes)?,
+        FirmwareFormat::Hex => parse_hex(&String::from_utf8_lossy(&bytes))?,
+        FirmwareFormat::Bin => {
+            let base = options
+                .base_address
+                .ok_or("base address is required for binary files")?;
+            if base as u64 + bytes.len() as u64 > 1 << 32 {
+                return Err("binary exceeds the 32-bit address range".to_string());
+            }
+            let mut image = Image::default();
+            image.push(base, &bytes);
+            image
+        }
+    };
+    let header = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let text = to_srec(image, &header, options)?;
+    SrecFile::parse(&text).map_err(|e| format!("converted S-record is invalid: {e}"))
+}
+
+fn detect(path: &Path, bytes: &[u8]) -> Result<FirmwareFormat, String> {
+    if bytes.starts_with(b"\x7fELF") {
+        return Ok(FirmwareFormat::Elf);
+    }
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "bin" => return Ok(FirmwareFormat::Bin),
+        "hex" | "ihex" | "ihx" => return Ok(FirmwareFormat::Hex),
+        "srec" | "s19" | "s28" | "s37" | "mot" => return Ok(FirmwareFormat::Srec),
+        _ => {}
+    }
+    // 拡張子で分からない場合は先頭の文字で判定する
+    match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b':') => Ok(FirmwareFormat::Hex),
+        Some(b'S') => Ok(FirmwareFormat::Srec),
+        _ => Err("unknown firmware format".to_string()),
+    }
+}
+
+// ELFのヘッダを読むための最小限の実装(32/64bit、両エンディアン)
+struct Elf<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+impl<'a> Elf<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        let is_64 = match bytes.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unsupported ELF class".to_string()),
+        };
+        let big_endian = match bytes.get(5) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unsupported ELF byte order".to_string()),
+        };
+        Ok(Self {
+            bytes,
+            is_64,
+            big_endian,
+        })
+    }
+    fn read(&self, offset: usize, size: usize) -> Result<u64, String> {
+        let field = self
+            .bytes
+            .get(offset..offset.saturating_add(size))
+            .ok_or("truncated ELF file")?;
+        let fold = |acc: u64, &b: &u8| (acc << 8) | b as u64;
+        Ok(if self.big_endian {
+            field.iter().fold(0, fold)
+        } else {
+            field.iter().rev().fold(0, fold)
+        })
+    }
+    // base から index 番目のエントリの位置(len バイトがファイル内に収まること)
+    // ファイルの値をそのまま足すとオーバーフローするので checked で計算する
+    fn entry(&self, base: usize, index: usize, size: usize, len: usize) -> Result<usize, String> {
+        index
+            .checked_mul(size)
+            .and_then(|offset| base.checked_add(offset))
+            .filter(|pos| {
+                pos.checked_add(len)
+                    .is_some_and(|end| end <= self.bytes.len())
+            })
+            .ok_or_else(|| "truncated ELF file".to_string())
+    }
+    // アドレス/オフセットのバイト数
+    fn word(&self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+    // (オフセット, エントリサイズ, 個数)
+    fn program_headers(&self) -> Result<(usize, usize, usize), String> {
+        let phoff = self.read(24 + self.word(), self.word())? as usize;
+        let (size, num) = if self.is_64 { (54, 56) } else { (42, 44) };
+        Ok((
+            phoff,
+            self.read(size, 2)? as usize,
+            self.read(num, 2)? as usize,
+        ))
+    }
+    fn section_headers(&self) -> Result<(usize, usize, usize), String> {
+        let shoff = self.read(24 + 2 * self.word(), self.word())? as usize;
+        let (size, num) = if self.is_64 { (58, 60) } else { (46, 48) };
+        Ok((
+            shoff,
+            self.read(size, 2)? as usize,
+            self.read(num, 2)? as usize,
+        ))
+    }
+    // (種類, オフセット, サイズ, リンク先)
+    fn section(&self, index: usize) -> Result<(u64, usize, usize, usize), String> {
+        let (shoff, shentsize, _) = self.section_headers()?;
+        let sh = self.entry(shoff, index, shentsize, if self.is_64 { 44 } else { 28 })?;
+        let kind = self.read(sh + 4, 4)?;
+        Ok(if self.is_64 {
+            (
+                kind,
+                self.read(sh + 24, 8)? as usize,
+                self.read(sh + 32, 8)? as usize,
+                self.read(sh + 40, 4)? as usize,
+            )
+        } else {
+            (
+                kind,
+                self.read(sh + 16, 4)? as usize,
+                self.read(sh + 20, 4)? as usize,
+                self.read(sh + 24, 4)? as usize,
+            )
+        })
+    }
+    fn c_str(&self, offset: usize) -> &'a [u8] {
+        let rest = self.bytes.get(offset..).unwrap_or_default();
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        &rest[..len]
+    }
+}
+
+// ELFのロード可能セグメント(PT_LOAD)を物理アドレスに配置する
+fn parse_elf(bytes: &[u8]) -> Result<Image, String> {
+    let elf = Elf::new(bytes)?;
+    let entry = elf.read(24, elf.word())?;
+    let (phoff, phentsize, phnum) = elf.program_headers()?;
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let ph = elf.entry(phoff, i, phentsize, if elf.is_64 { 40 } else { 20 })?;
+        // PT_LOAD
+        if elf.read(ph, 4)? != 1 {
+            continue;
+        }
+        let (offset, paddr, filesz) = if elf.is_64 {
+            (
+                elf.read(ph + 8, 8)?,
+                elf.read(ph + 24, 8)?,
+                elf.read(ph + 32, 8)?,
+            )
+        } else {
+            (
+                elf.read(ph + 4, 4)?,
+                elf.read(ph + 12, 4)?,
+                elf.read(ph + 16, 4)?,
+            )
+        };
+        if filesz == 0 {
+            continue;
+        }
+        if paddr.saturating_add(filesz) > 1 << 32 {
+            return Err(format!(
+                "segment at {paddr:#X} exceeds the 32-bit address range"
+            ));
+        }
+        let data = bytes
+            .get(offset as usize..offset.saturating_add(filesz) as usize)
+            .ok_or("truncated ELF segment")?;
+        segments.push((paddr as u32, data));
+    }
+    if segments.is_empty() {
+        return Err("no loadable segments".to_string());
+    }
+    segments.sort_by_key(|(address, _)| *address);
+    let mut image = Image {
+        entry: u32::try_from(entry).ok(),
+        ..Default::default()
+    };
+    for (address, data) in segments {
+        image.push(address, data);
+    }
+    Ok(image)
+}
+
+// ELFのシンボルテーブルからアドレスを探す(ELF以外は None)
+pub fn symbol_address(path: impl AsRef<Path>, name: &str) -> Result<Option<u32>, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if !bytes.starts_with(b"\x7fELF") {
+        return Ok(None);
+    }
+    let elf = Elf::new(&bytes)?;
+    let (_, _, shnum) = elf.section_headers()?;
+    let entsize = if elf.is_64 { 24 } else { 16 };
+    for index in 0..shnum {
+        let (kind, offset, size, link) = elf.section(index)?;
+        // SHT_SYMTAB
+        if kind != 2 {
+            continue;
+        }
+        let (_, strtab, _, _) = elf.section(link)?;
+        for i in 0..size / entsize {
+            let sym = elf.entry(offset, i, entsize, entsize)?;
+            let name_offset = elf.read(sym, 4)? as usize;
+            let name_offset = strtab
+                .checked_add(name_offset)
+                .ok_or("truncated ELF file")?;
+            if elf.c_str(name_offset) != name.as_bytes() {
+                continue;
+            }
+            let value = if elf.is_64 {
+                elf.read(sym + 8, 8)?
+            } else {
+                elf.read(sym + 4, 4)?
+            };
+            return u32::try_from(value)
+                .map(Some)
+                .map_err(|_| format!("symbol {name} is outside the 32-bit address range"));
+        }
+    }
+    Err(format!("symbol {name} not found"))
+}
+
+// Intel HEX(拡張セグメント/リニアアドレスに対応)
+fn parse_hex(text: &str) -> Result<Image, String> {
+    let mut image = Image::default();
+    let mut base = 0u32;
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let body = raw
+            .strip_prefix(':')
+            .filter(|body| body.is_ascii() && body.len() % 2 == 0 && body.len() >= 10)
+            .ok_or_else(|| format!("line {line}: not an Intel HEX record"))?;
+        let values = (0..body.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&body[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("line {line}: invalid hex digit"))?;
+        let count = values[0] as usize;
+        if values.len() != count + 5 {
+            return Err(format!("line {line}: byte count does not match"));
+        }
+        if values.iter().fold(0u8, |acc, &v| acc.wrapping_add(v)) != 0 {
+            return Err(format!("line {line}: checksum mismatch"));
+        }
+        let offset = u16::from_be_bytes([values[1], values[2]]) as u32;
+        let data = &values[4..4 + count];
+        let value = data.iter().fold(0u32, |acc, &v| (acc << 8) | v as u32);
+        match values[3] {
+            0x00 => image.push(base.wrapping_add(offset), data),
+            0x01 => break,
+            0x02 => base = value << 4,
+            // CS:IP
+            0x03 => image.entry = Some(((value >> 16) << 4) + (value & 0xFFFF)),
+            0x04 => base = value << 16,
+            0x05 => image.entry = Some(value),
+            kind => return Err(format!("line {line}: unknown record type {kind:02X}")),
+        }
+    }
+    if image.segments.is_empty() {
+        return Err("no data records".to_string());
+    }
+    Ok(image)
+}
+
+// S0(ヘッダ)、データ、S7〜S9(開始アドレス)の順に出力する
+fn to_srec(mut image: Image, header: &str, options: &ConvertOptions) -> Result<String, String> {
+    image.segments.sort_by_key(|(address, _)| *address);
+    let end = image
+        .segments
+        .iter()
+        .map(|(address, data)| *address as u64 + data.len() as u64 - 1)
+        .max()
+        .unwrap_or(0)
+        .max(image.entry.unwrap_or(0) as u64);
+    let kind = match options.address_width {
+        AddressWidth::Auto if end <= 0xFFFF => 1,
+        AddressWidth::Auto if end <= 0xFF_FFFF => 2,
+        AddressWidth::Auto => 3,
+        AddressWidth::Bits16 => 1,
+        AddressWidth::Bits24 => 2,
+        AddressWidth::Bits32 => 3,
+    };
+    let addr_len = kind as usize + 1;
+    if end >= 1 << (addr_len * 8) {
+        return Err(format!(
+            "address {end:#X} does not fit in {}-bit S-records",
+            addr_len * 8
+        ));
+    }
+    // カウント(1バイト)にアドレスとチェックサムも含まれる
+    let max_size = 255 - addr_len - 1;
+    if options.record_size == 0 || options.record_size > max_size {
+        return Err(format!("record size must be between 1 and {max_size}"));
+    }
+    let header = header.as_bytes();
+    let mut text = record(0, 2, 0, &header[..header.len().min(MAX_HEADER_LEN)]);
+    for (address, data) in &image.segments {
+        for (i, chunk) in data.chunks(options.record_size).enumerate() {
+            let address = address + (i * options.record_size) as u32;
+            text += &record(kind, addr_len, address, chunk);
+        }
+    }
+    text += &record(10 - kind, addr_len, image.entry.unwrap_or(0), &[]);
+    Ok(text)
+}
+
+fn record(kind: u8, addr_len: usize, address: u32, data: &[u8]) -> String {
+    let mut values = vec![(addr_len + data.len() + 1) as u8];
+    values.extend_from_slice(&address.to_be_bytes()[4 - addr_len..]);
+    values.extend_from_slice(data);
+    let sum = values.iter().fold(0u8, |acc, &v| acc.wrapping_add(v));
+    values.push(!sum);
+    let hex = values
+        .iter()
+        .map(|v| format!("{v:02X}"))
+        .collect::<String>();
+    format!("S{kind}{hex}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_image(text: &str) -> Image {
+        parse_hex(text).unwrap()
+    }
+
+    #[test]
+    fn parse_hex_data_and_end() {
+        let image = hex_image(":0400000001020304F2\n:00000001FF\n");
+        assert_eq!(image.segments, vec![(0, vec![1, 2, 3, 4])]);
+        assert_eq!(image.entry, None);
+    }
+
+    #[test]
+    fn parse_hex_extended_linear_address() {
+        let image = hex_image(":020000040001F9\n:0400000001020304F2\n:00000001FF\n");
+        assert_eq!(image.segments, vec![(0x1_0000, vec![1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn parse_hex_rejects_bad_records() {
+        assert!(parse_hex(":0400000001020304F3\n")
+            .unwrap_err()
+            .contains("checksum"));
+        assert!(parse_hex(":0400000001020304\n").is_err());
+        assert!(parse_hex("0400000001020304F2\n").is_err());
+        assert!(parse_hex(":00000001FF\n").is_err());
+    }
+
+    #[test]
+    fn to_srec_picks_address_width() {
+        let options = ConvertOptions::default();
+        let text = to_srec(hex_image(":0400000001020304F2\n"), "", &options).unwrap();
+        assert_eq!(text, "S0030000FC\nS107000001020304EE\nS9030000FC\n");
+        let image = hex_image(":020000040001F9\n:0400000001020304F2\n");
+        let text = to_srec(image, "", &options).unwrap();
+        assert!(text.lines().nth(1).unwrap().starts_with("S208010000"));
+        assert!(text.lines().last().unwrap().starts_with("S8"));
+        SrecFile::parse(&text).unwrap();
+    }
+
+    #[test]
+    fn to_srec_rejects_out_of_range() {
+        let options = ConvertOptions {
+            address_width: AddressWidth::Bits16,
+            ..Default::default()
+        };
+        let image = hex_image(":020000040001F9\n:0400000001020304F2\n");
+        assert!(to_srec(image, "", &options).is_err());
+        let options = ConvertOptions {
+            record_size: 0,
+            ..Default::default()
+        };
+        assert!(to_srec(hex_image(":0400000001020304F2\n"), "", &options).is_err());
+    }
+
+    #[test]
+    fn truncated_elf_header() {
+        assert!(parse_elf(b"\x7fELF\x01\x01\x01").is_err());
+        assert!(parse_elf(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn elf_offsets_out_of_range() {
+        // 64bit, リトルエンディアン、プログラムヘッダのオフセットが末尾近く
+        let mut header = vec![0u8; 64];
+        header[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        header[32..40].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        header[54..56].copy_from_slice(&56u16.to_le_bytes());
+        header[56..58].copy_from_slice(&2u16.to_le_bytes());
+        // セクションヘッダも同様
+        header[40..48].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        header[58..60].copy_from_slice(&64u16.to_le_bytes());
+        header[60..62].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(parse_elf(&header).unwrap_err(), "truncated ELF file");
+        let elf = Elf::new(&header).unwrap();
+        assert!(elf.section(1).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+// ファームウェアの識別情報(バージョン、バリアント、CRC32)と書き込み前の確認
+use crate::srec::{SrecFile, SrecSummary};
+use std::cmp::Ordering;
+
+// "MIQS-A v1.2.3" のような文字列(variant/version の名前付きグループを使う)
+pub const DEFAULT_VERSION_PATTERN: &str =
+    r"(?P<variant>MIQS[\w-]*?)[ _-]+v(?P<version>\d+(?:\.\d+)+)";
+// シンボルが指す文字列の最大長
+const MAX_SYMBOL_STRING: usize = 128;
+// イメージ中の文字列として扱う最小の長さ
+const MIN_STRING_LEN: usize = 4;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct VersionOptions {
+    // 未設定なら既定のパターン
+    pub pattern: Option<String>,
+    // ELFでバージョン文字列を指すシンボル(例: firmware_version)
+    pub symbol: Option<String>,
+}
+impl VersionOptions {
+    pub fn parser(&self) -> Result<VersionParser, String> {
+        match self.pattern.as_deref() {
+            Some(pattern) => VersionParser::new(pattern),
+            None => Ok(VersionParser::default()),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct FirmwareVersion {
+    pub variant: Option<String>,
+    pub version: String,
+}
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.variant {
+            Some(variant) => write!(f, "{variant} v{}", self.version),
+            None => write!(f, "v{}", self.version),
+        }
+    }
+}
+
+pub struct VersionParser {
+    regex: regex::Regex,
+}
+impl VersionParser {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = regex::Regex::new(pattern).map_err(|e| e.to_string())?;
+        if !regex.capture_names().any(|name| name == Some("version")) {
+            return Err("version pattern must have a (?P<version>...) group".to_string());
+        }
+        Ok(Self { regex })
+    }
+    pub fn find(&self, text: &str) -> Option<FirmwareVersion> {
+        let caps = self.regex.captures(text)?;
+        Some(FirmwareVersion {
+            variant: caps.name("variant").map(|m| m.as_str().to_string()),
+            version: caps.name("version")?.as_str().to_string(),
+        })
+    }
+}
+impl Default for VersionParser {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERSION_PATTERN).unwrap()
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FirmwareInfo {
+    pub header: Option<String>,
+    pub version: Option<FirmwareVersion>,
+    // データの合計バイト数
+    pub image_size: usize,
+    // アドレス順に連結したデータのCRC32
+    pub crc32: u32,
+}
+impl std::fmt::Display for FirmwareInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => writeln!(f, "version: {version}")?,
+            None => writeln!(f, "version: unknown")?,
+        }
+        write!(f, "size: {} bytes, crc32: {:08X}", self.image_size, self.crc32)
+    }
+}
+
+// 書き込み前にGUIへ表示する内容
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FirmwareReport {
+    #[serde(flatten)]
+    pub summary: SrecSummary,
+    pub info: FirmwareInfo,
+    // ボードが報告した実行中のバージョン
+    pub running: Option<FirmwareVersion>,
+    pub warnings: Vec<String>,
+}
+
+// symbol_address: バージョン文字列を指すシンボルのアドレス(ELFのみ)
+fn inspect(srec: &SrecFile, parser: &VersionParser, symbol_address: Option<u32>) -> FirmwareInfo {
+    let header = srec.summary().header;
+    let segments = srec.segments();
+    let version = symbol_address
+        .and_then(|address| read_string(&segments, address))
+        .map(|text| {
+            parser.find(&text).unwrap_or(FirmwareVersion {
+                variant: None,
+                version: text,
+            })
+        })
+        .or_else(|| header.as_deref().and_then(|header| parser.find(header)))
+        .or_else(|| find_in_image(&segments, parser));
+    FirmwareInfo {
+        header,
+        version,
+        image_size: segments.iter().map(|(_, data)| data.len()).sum(),
+        crc32: crc32(segments.iter().flat_map(|(_, data)| data.iter().copied())),
+    }
+}
+
+// 指定アドレスのNUL終端文字列
+fn read_string(segments: &[(u32, Vec<u8>)], address: u32) -> Option<String> {
+    let (start, data) = segments.iter().find(|(start, data)| {
+        address >= *start && ((address - start) as usize) < data.len()
+    })?;
+    let rest = &data[(address - start) as usize..];
+    let len = rest
+        .iter()
+        .take(MAX_SYMBOL_STRING)
+        .position(|&b| b == 0)
+        .unwrap_or(rest.len().min(MAX_SYMBOL_STRING));
+    let text = String::from_utf8_lossy(&rest[..len]).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// イメージ中の表示可能な文字列からバージョンを探す
+fn find_in_image(segments: &[(u32, Vec<u8>)], parser: &VersionParser) -> Option<FirmwareVersion> {
+    segments.iter().find_map(|(_, data)| {
+        data.split(|&b| !(b.is_ascii_graphic() || b == b' '))
+            .filter(|s| s.len() >= MIN_STRING_LEN)
+            .find_map(|s| parser.find(&String::from_utf8_lossy(s)))
+    })
+}
+
+// CRC-32 (IEEE 802.3)
+fn crc32(data: impl Iterator<Item = u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// "1.2.10" を数値として比較する(数値でない部分があれば比較しない)
+fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let parse = |v: &str| {
+        v.split('.')
+            .map(|n| n.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    Some(parse(a)?.cmp(&parse(b)?))
+}
+
+// バリアント違いとダウングレードを警告する
+fn check(image: &FirmwareInfo, running: Option<&FirmwareVersion>) -> Vec<String> {
+    let (Some(new), Some(running)) = (image.version.as_ref(), running) else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    if let (Some(a), Some(b)) = (&new.variant, &running.variant) {
+        if a != b {
+            warnings.push(format!(
+                "variant mismatch: image is {a}, board is running {b}"
+            ));
+        }
+    }
+    if compare_versions(&new.version, &running.version) == Some(Ordering::Less) {
+        warnings.push(format!(
+            "downgrade: image v{} is older than running v{}",
+            new.version, running.version
+        ));
+    }
+    warnings
+}
+
+// ボードが起動時のprintfで報告したバージョン
+pub struct BoardFirmware {
+    parser: VersionParser,
+    running: Option<FirmwareVersion>,
+}
+impl BoardFirmware {
+    pub fn new(parser: VersionParser) -> Self {
+        Self {
+            parser,
+            running: None,
+        }
+    }
+    // バージョンが変わった場合に返す
+    pub fn observe(&mut self, text: &str) -> Option<FirmwareVersion> {
+        let version = self.parser.find(text)?;
+        if self.running.as_ref() == Some(&version) {
+            return None;
+        }
+        self.running = Some(version.clone());
+        Some(version)
+    }
+    pub fn running(&self) -> Option<&FirmwareVersion> {
+        self.running.as_ref()
+    }
+    pub fn clear(&mut self) {
+        self.running = None;
+    }
+    pub fn set_parser(&mut self, parser: VersionParser) {
+        self.parser = parser;
+    }
+}
+
+// ファイルから識別情報を取り出し、実行中のバージョンと比べる
+pub fn report(
+    path: &str,
+    srec: &SrecFile,
+    options: &VersionOptions,
+    running: Option<FirmwareVersion>,
+) -> Result<FirmwareReport, String> {
+    let parser = options.parser()?;
+    // シンボルが見つからなくても書き込みは止めない(ヘッダやイメージ中の文字列から探す)
+    let symbol_address = match options.symbol.as_deref() {
+        Some(name) => crate::firmware::symbol_address(path, name).unwrap_or_else(|e| {
+            log::warn!("{path}: {e}");
+            None
+        }),
+        None => None,
+    };
+    let info = inspect(srec, &parser, symbol_address);
+    // 起動時のprintfを受け取っていなければ実行中のバージョンは不明なので比較しない
+    if running.is_none() {
+        log::info!("running firmware version is unknown; skipping version check");
+    }
+    let warnings = check(&info, running.as_ref());
+    Ok(FirmwareReport {
+        summary: srec.summary(),
+        info,
+        running,
+        warnings,
+    })
+}
@@ -0,0 +1,105 @@
+// ファームウェアの再ビルドを検知して自動で書き込む(GUIの監視モード)
+use crate::commands::{self, InternalCommand};
+use crate::file_watch::{FileWatcher, DEFAULT_DEBOUNCE_MS};
+use crate::AppState;
+use std::time::Duration;
+use tauri::Manager;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WatchOptions {
+    pub debounce_ms: u64,
+    // 書き込み後に読み込み済みのMIDIファイルを送り直す
+    pub reupload_midi: bool,
+}
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            reupload_midi: false,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatchStage {
+    Watching,
+    Changed,
+    Flashing,
+    Uploading,
+    Done,
+    Failed,
+    Stopped,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct WatchEvent<'a> {
+    path: &'a str,
+    stage: WatchStage,
+    message: Option<String>,
+}
+
+pub fn notify<R: tauri::Runtime>(
+    manager: &impl Manager<R>,
+    path: &str,
+    stage: WatchStage,
+    message: Option<String>,
+) {
+    match &message {
+        Some(message) => log::warn!("firmware watch ({path}): {message}"),
+        None => log::info!("firmware watch ({path}): {stage:?}"),
+    }
+    let _ = manager.emit_all(
+        "firmware-watch",
+        WatchEvent {
+            path,
+            stage,
+            message,
+        },
+    );
+}
+
+// 停止されるまで監視を続ける(失敗しても次の更新を待つ)
+pub async fn watch_firmware(app_handle: tauri::AppHandle, path: String, options: WatchOptions) {
+    let mut watcher = FileWatcher::new(&path, Duration::from_millis(options.debounce_ms));
+    notify(&app_handle, &path, WatchStage::Watching, None);
+    loop {
+        watcher.wait_for_change().await;
+        notify(&app_handle, &path, WatchStage::Changed, None);
+        match reflash(&app_handle, &path, &options).await {
+            Ok(_) => notify(&app_handle, &path, WatchStage::Done, None),
+            Err(e) => notify(&app_handle, &path, WatchStage::Failed, Some(e)),
+        }
+    }
+}
+
+// 検証してからローダの起動を待って書き込む(不正なファイルやバリアント違いは書き込まない)
+async fn reflash(
+    app_handle: &tauri::AppHandle,
+    path: &str,
+    options: &WatchOptions,
+) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let (srec, _, flash_options) = commands::load_srec_for_flash(&state, path, false).await?;
+    notify(app_handle, path, WatchStage::Flashing, None);
+    commands::request(&state, |reply| InternalCommand::BootFlash {
+        srec,
+        options: flash_options,
+        reply,
+    })
+    .await?;
+    if options.reupload_midi {
+        let data = state
+            .file_data
+            .lock()
+            .await
+            .as_ref()
+            .map(|file| file.data.clone());
+        if let Some(data) = data {
+            notify(app_handle, path, WatchStage::Uploading, None);
+            commands::request(&state, |reply| InternalCommand::Send { data, reply }).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,450 @@
+// SRECファイルの書き込み(1レコードずつ送り、ローダの応答を確認する)
+use crate::serial_com::{self, DeviceStatus, Message};
+use crate::srec::SrecFile;
+use serial2_tokio::SerialPort;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+#[derive(
+    serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlashMode {
+    // 全体を一度に送る(従来の動作)
+    Raw,
+    // 1行ごとに待ち時間を入れる
+    #[default]
+    Paced,
+    // 1行ごとにローダの応答を待つ
+    Ack,
+}
+
+// ボードをローダへ入れるためのリセット方法
+#[derive(
+    serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResetLine {
+    // 手動でリセットする
+    #[default]
+    None,
+    Dtr,
+    Rts,
+    DtrRts,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FlashOptions {
+    pub mode: FlashMode,
+    pub line_delay_ms: u64,
+    // Ackモードでローダが返すバイト
+    pub ack_byte: u8,
+    pub ack_timeout_ms: u64,
+    // 送信後に load success/failed を待つ時間
+    pub result_timeout_ms: u64,
+    // 以下はローダの起動待ちから行う書き込みで使う
+    pub reset: ResetLine,
+    // 制御線をアサートしておく時間
+    pub reset_pulse_ms: u64,
+    pub loader_timeout_ms: u64,
+    // load success の後に Sequencer ready を待つ時間
+    pub ready_timeout_ms: u64,
+}
+impl Default for FlashOptions {
+    fn default() -> Self {
+        Self {
+            mode: FlashMode::default(),
+            line_delay_ms: 5,
+            ack_byte: 0x06,
+            ack_timeout_ms: 1000,
+            result_timeout_ms: 10000,
+            reset: ResetLine::default(),
+            reset_pulse_ms: 100,
+            loader_timeout_ms: 10000,
+            ready_timeout_ms: 10000,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FlashProgress {
+    pub records_sent: usize,
+    pub total_records: usize,
+    pub bytes_sent: usize,
+    pub total_bytes: usize,
+    // 残り時間の見積もり(ミリ秒)
+    pub eta_ms: Option<u64>,
+}
+
+// ローダの起動待ちから行う書き込みの進行段階
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BootStage {
+    Reset,
+    WaitLoader,
+    Transfer,
+    WaitLoad,
+    WaitReady,
+    Done,
+}
+impl std::fmt::Display for BootStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reset => write!(f, "Reset Board"),
+            Self::WaitLoader => write!(f, "Wait for Loader"),
+            Self::Transfer => write!(f, "Transfer SREC"),
+            Self::WaitLoad => write!(f, "Wait for Load Result"),
+            Self::WaitReady => write!(f, "Wait for Sequencer"),
+            Self::Done => write!(f, "Done"),
+        }
+    }
+}
+
+pub enum BootEvent<'a> {
+    Stage(BootStage),
+    Progress(&'a FlashProgress),
+    // Sequencer ready を待つ間に届いたprintfなど
+    Message(Message),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlashError {
+    Io(String),
+    LoadFailed,
+    NoAck {
+        line: usize,
+    },
+    // 最後のレコードを送る前に load success を受け取った
+    EarlyLoadSuccess {
+        records_sent: usize,
+        total_records: usize,
+    },
+    // タイムアウトした段階
+    Timeout(BootStage),
+}
+impl std::fmt::Display for FlashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(msg) => write!(f, "{msg}"),
+            Self::LoadFailed => write!(f, "{}", DeviceStatus::LoadFailed),
+            Self::NoAck { line } => write!(f, "no acknowledgement for line {line}"),
+            Self::EarlyLoadSuccess {
+                records_sent,
+                total_records,
+            } => write!(
+                f,
+                "load success received after only {records_sent} of {total_records} records"
+            ),
+            Self::Timeout(BootStage::WaitLoader) => write!(f, "timed out waiting for the loader"),
+            Self::Timeout(BootStage::WaitReady) => write!(f, "timed out waiting for the sequencer"),
+            Self::Timeout(_) => write!(f, "timed out waiting for the loader result"),
+        }
+    }
+}
+impl From<std::io::Error> for FlashError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value.to_string())
+    }
+}
+impl From<String> for FlashError {
+    fn from(value: String) -> Self {
+        Self::Io(value)
+    }
+}
+
+// 送信済みのレコード数(書き込み中のものを含む)
+#[derive(Clone, Copy)]
+struct Sent {
+    records: usize,
+    total: usize,
+}
+
+// 送信中に届いたバイトを確認する(load failed なら中断、load success なら true)
+// 最後のレコードの直後に届いた load success を読み捨てないようにする
+// 途中で届いた load success はイメージが欠けているので失敗とする
+fn check_response(value: u8, sent: Sent) -> Result<bool, FlashError> {
+    match DeviceStatus::from_byte(value) {
+        Some(DeviceStatus::LoadFailed) => Err(FlashError::LoadFailed),
+        Some(DeviceStatus::LoadSuccess) if sent.records < sent.total => {
+            Err(FlashError::EarlyLoadSuccess {
+                records_sent: sent.records,
+                total_records: sent.total,
+            })
+        }
+        Some(DeviceStatus::LoadSuccess) => Ok(true),
+        _ => {
+            log::debug!("ignored loader response: {value:#04x}");
+            Ok(false)
+        }
+    }
+}
+
+// 待ち時間の間もローダからの応答を監視する(load success を受け取ったら true)
+async fn pace(port: &mut SerialPort, delay: Duration, sent: Sent) -> Result<bool, FlashError> {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+    loop {
+        tokio::select!(
+            _ = &mut sleep => return Ok(false),
+            v = serial_com::receive_byte(port) => {
+                if check_response(v?, sent)? {
+                    return Ok(true);
+                }
+            }
+        );
+    }
+}
+
+// ACK を待つ(ACKの代わりに load success を受け取ったら true)
+async fn wait_ack(
+    port: &mut SerialPort,
+    line: usize,
+    options: &FlashOptions,
+    sent: Sent,
+) -> Result<bool, FlashError> {
+    let wait = async {
+        loop {
+            let v = serial_com::receive_byte(port).await?;
+            if v == options.ack_byte {
+                return Ok::<bool, FlashError>(false);
+            }
+            if check_response(v, sent)? {
+                return Ok(true);
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_millis(options.ack_timeout_ms), wait)
+        .await
+        .map_err(|_| FlashError::NoAck { line })?
+}
+
+async fn wait_result(port: &mut SerialPort, timeout: Duration) -> Result<(), FlashError> {
+    let wait = async {
+        loop {
+            match DeviceStatus::from_byte(serial_com::receive_byte(port).await?) {
+                Some(DeviceStatus::LoadSuccess) => return Ok::<(), FlashError>(()),
+                Some(DeviceStatus::LoadFailed) => return Err(FlashError::LoadFailed),
+                _ => continue,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| FlashError::Timeout(BootStage::WaitLoad))?
+}
+
+// 制御線を一定時間アサートしてボードをリセットする
+async fn reset_board(port: &mut SerialPort, options: &FlashOptions) -> Result<(), FlashError> {
+    let (dtr, rts) = match options.reset {
+        ResetLine::None => return Ok(()),
+        ResetLine::Dtr => (true, false),
+        ResetLine::Rts => (false, true),
+        ResetLine::DtrRts => (true, true),
+    };
+    // リセット前に届いていたバイトを起動通知と誤認しないよう捨てる
+    port.discard_input_buffer()?;
+    if dtr {
+        port.set_dtr(true)?;
+    }
+    if rts {
+        port.set_rts(true)?;
+    }
+    tokio::time::sleep(Duration::from_millis(options.reset_pulse_ms)).await;
+    if dtr {
+        port.set_dtr(false)?;
+    }
+    if rts {
+        port.set_rts(false)?;
+    }
+    Ok(())
+}
+
+// ローダの起動通知(0x0)を待つ(それ以外のバイトは読み捨てる)
+async fn wait_loader(port: &mut SerialPort, timeout: Duration) -> Result<(), FlashError> {
+    let wait = async {
+        loop {
+            let v = serial_com::receive_byte(port).await?;
+            if DeviceStatus::from_byte(v) == Some(DeviceStatus::LoaderWait) {
+                return Ok::<(), FlashError>(());
+            }
+            log::debug!("ignored byte while waiting for the loader: {v:#04x}");
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| FlashError::Timeout(BootStage::WaitLoader))?
+}
+
+// シーケンサの起動通知(0xE)を待つ(起動時のprintfはメッセージとして解析する)
+async fn wait_ready(
+    port: &mut SerialPort,
+    timeout: Duration,
+    on_event: &mut impl FnMut(BootEvent<'_>),
+) -> Result<(), FlashError> {
+    let wait = async {
+        loop {
+            let first = serial_com::receive_byte(port).await?;
+            match serial_com::receive_sequence_msg(first, port).await? {
+                Some(Message::Status(DeviceStatus::SequencerReady)) => {
+                    return Ok::<(), FlashError>(())
+                }
+                Some(Message::Status(DeviceStatus::LoadFailed)) => {
+                    return Err(FlashError::LoadFailed)
+                }
+                Some(Message::Status(_)) | None => continue,
+                Some(msg) => on_event(BootEvent::Message(msg)),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| FlashError::Timeout(BootStage::WaitReady))?
+}
+
+// 書き込んで load success(0xD) を受け取るまで待つ
+pub async fn flash_srec(
+    port: &mut SerialPort,
+    srec: &SrecFile,
+    options: &FlashOptions,
+    on_progress: impl FnMut(&FlashProgress),
+) -> Result<(), FlashError> {
+    if send_records(port, srec, options, on_progress).await? {
+        return Ok(());
+    }
+    wait_result(port, Duration::from_millis(options.result_timeout_ms)).await
+}
+
+// ローダの起動待ち(必要ならリセット)から書き込み、Sequencer ready までを順に行う
+// loader_waiting: 既に Loader start wait を受け取っていれば起動待ちを省く
+pub async fn boot_flash(
+    port: &mut SerialPort,
+    srec: &SrecFile,
+    options: &FlashOptions,
+    loader_waiting: bool,
+    mut on_event: impl FnMut(BootEvent<'_>),
+) -> Result<(), FlashError> {
+    let reset = options.reset != ResetLine::None;
+    if reset {
+        on_event(BootEvent::Stage(BootStage::Reset));
+        reset_board(port, options).await?;
+    }
+    if reset || !loader_waiting {
+        on_event(BootEvent::Stage(BootStage::WaitLoader));
+        wait_loader(port, Duration::from_millis(options.loader_timeout_ms)).await?;
+    }
+    on_event(BootEvent::Stage(BootStage::Transfer));
+    let loaded = send_records(port, srec, options, |progress: &FlashProgress| {
+        on_event(BootEvent::Progress(progress))
+    })
+    .await?;
+    on_event(BootEvent::Stage(BootStage::WaitLoad));
+    if !loaded {
+        wait_result(port, Duration::from_millis(options.result_timeout_ms)).await?;
+    }
+    on_event(BootEvent::Stage(BootStage::WaitReady));
+    wait_ready(
+        port,
+        Duration::from_millis(options.ready_timeout_ms),
+        &mut on_event,
+    )
+    .await?;
+    on_event(BootEvent::Stage(BootStage::Done));
+    Ok(())
+}
+
+// 最後のレコードの後に load success を受け取った場合は true
+async fn send_records(
+    port: &mut SerialPort,
+    srec: &SrecFile,
+    options: &FlashOptions,
+    mut on_progress: impl FnMut(&FlashProgress),
+) -> Result<bool, FlashError> {
+    let total_records = srec.records.len();
+    let total_bytes = srec.records.iter().map(|r| r.text.len() + 1).sum();
+    let mut progress = FlashProgress {
+        records_sent: 0,
+        total_records,
+        bytes_sent: 0,
+        total_bytes,
+        eta_ms: None,
+    };
+    let start = Instant::now();
+    if options.mode == FlashMode::Raw {
+        port.write_all(srec.to_text().as_bytes()).await?;
+        progress.records_sent = total_records;
+        progress.bytes_sent = total_bytes;
+        progress.eta_ms = Some(0);
+        on_progress(&progress);
+    } else {
+        let delay = Duration::from_millis(options.line_delay_ms);
+        for record in &srec.records {
+            log::trace!(target: crate::logger::RAW_TARGET, "tx {}", record.text);
+            port.write_all(record.text.as_bytes()).await?;
+            port.write_all(b"\n").await?;
+            let sent = Sent {
+                records: progress.records_sent + 1,
+                total: total_records,
+            };
+            let loaded = match options.mode {
+                FlashMode::Ack => wait_ack(port, record.line, options, sent).await?,
+                _ => pace(port, delay, sent).await?,
+            };
+            progress.records_sent += 1;
+            progress.bytes_sent += record.text.len() + 1;
+            let elapsed = start.elapsed().as_millis() as u64;
+            let remaining = (total_bytes - progress.bytes_sent) as u64;
+            progress.eta_ms = Some(elapsed * remaining / progress.bytes_sent as u64);
+            on_progress(&progress);
+            if loaded {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOAD_SUCCESS: u8 = 0xD;
+    const LOAD_FAILED: u8 = 0xA;
+
+    #[test]
+    fn load_success_after_last_record() {
+        let sent = Sent {
+            records: 3,
+            total: 3,
+        };
+        assert_eq!(check_response(LOAD_SUCCESS, sent), Ok(true));
+    }
+
+    #[test]
+    fn early_load_success_fails() {
+        let sent = Sent {
+            records: 1,
+            total: 3,
+        };
+        assert_eq!(
+            check_response(LOAD_SUCCESS, sent),
+            Err(FlashError::EarlyLoadSuccess {
+                records_sent: 1,
+                total_records: 3
+            })
+        );
+    }
+
+    #[test]
+    fn load_failed_and_other_bytes() {
+        let sent = Sent {
+            records: 1,
+            total: 3,
+        };
+        assert_eq!(
+            check_response(LOAD_FAILED, sent),
+            Err(FlashError::LoadFailed)
+        );
+        assert_eq!(check_response(b'x', sent), Ok(false));
+    }
+}
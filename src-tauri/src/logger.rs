@@ -0,0 +1,194 @@
+// ログ出力(レベル/モジュール別のフィルタ、ローテーションするログファイル)
+// フィルタは "info,serial_com=debug,serial_raw=trace" の形式で指定する
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// 環境変数で設定ファイルの指定を上書きできる
+pub const LOG_ENV: &str = "MIQS_LOG";
+// シリアル通信の生データ(trace で有効になる)
+pub const RAW_TARGET: &str = "serial_raw";
+const DEFAULT_FILTER: &str = "info";
+const CRATE_PREFIX: &str = "miqs_music_prot::";
+const LOG_DIR: &str = "miqs_music_prot";
+const LOG_FILE: &str = "miqs.log";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+// 現在のファイルを含めて保持する数
+const MAX_LOG_FILES: usize = 3;
+
+struct Filter {
+    default: LevelFilter,
+    // (モジュール名, レベル)
+    directives: Vec<(String, LevelFilter)>,
+}
+impl Filter {
+    fn parse(spec: &str) -> Self {
+        let mut filter = Self {
+            default: LevelFilter::Info,
+            directives: Vec::new(),
+        };
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.directives.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = part.parse() {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        // 長い(具体的な)指定を優先する
+        filter.directives.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        filter
+    }
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let target = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+        self.directives
+            .iter()
+            .find(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+impl RotatingFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+    fn write_line(&mut self, line: &str) {
+        if self.size + line.len() as u64 > MAX_LOG_SIZE {
+            self.rotate();
+        }
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+    // miqs.log -> miqs.log.1 -> miqs.log.2 ...
+    fn rotate(&mut self) {
+        for i in (1..MAX_LOG_FILES).rev() {
+            let _ = std::fs::rename(rotated_path(&self.path, i - 1), rotated_path(&self.path, i));
+        }
+        if let Ok(file) = File::create(&self.path) {
+            self.file = file;
+            self.size = 0;
+        }
+    }
+}
+
+// 0 は現在のファイル
+fn rotated_path(path: &std::path::Path, index: usize) -> PathBuf {
+    if index == 0 {
+        path.to_path_buf()
+    } else {
+        PathBuf::from(format!("{}.{index}", path.display()))
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    file: Mutex<Option<RotatingFile>>,
+    // 標準エラー出力にも出す(CLIでは画面を崩さないよう出さない)
+    console: bool,
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let target = record.target();
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            target.strip_prefix(CRATE_PREFIX).unwrap_or(target),
+            record.args()
+        );
+        if self.console {
+            eprint!("{line}");
+        }
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            file.write_line(&line);
+        }
+    }
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+pub fn log_path() -> Option<PathBuf> {
+    tauri::api::path::data_local_dir().map(|dir| dir.join(LOG_DIR).join("logs").join(LOG_FILE))
+}
+
+// 環境変数 > 設定ファイル > 既定値 の順でフィルタを決める
+pub fn init(config_filter: Option<&str>, console: bool) {
+    let spec = std::env::var(LOG_ENV)
+        .ok()
+        .or_else(|| config_filter.map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_FILTER.to_string());
+    let filter = Filter::parse(&spec);
+    let max_level = filter.max_level();
+    let file = log_path().and_then(|path| RotatingFile::open(path).ok());
+    let logger = Logger {
+        filter,
+        file: Mutex::new(file),
+        console,
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+// 保持しているログ(古い順)と環境情報を1つのファイルにまとめる
+pub fn export(dest: &std::path::Path) -> Result<(), String> {
+    log::logger().flush();
+    let path = log_path().ok_or("log directory not found")?;
+    let mut out = File::create(dest).map_err(|e| e.to_string())?;
+    writeln!(
+        out,
+        "{} {} ({} {})",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .map_err(|e| e.to_string())?;
+    for i in (0..MAX_LOG_FILES).rev() {
+        let Ok(text) = std::fs::read_to_string(rotated_path(&path, i)) else {
+            continue;
+        };
+        out.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -3,16 +3,34 @@
 
 mod cli;
 mod commands;
+mod config;
+mod connection;
+mod detect;
+mod device_printf;
+mod file_watch;
+mod firmware;
+mod firmware_info;
+mod firmware_watch;
+mod flash;
+mod logger;
+mod midi_excerpt;
+mod midi_reload;
+mod playlist;
+mod port_watch;
 mod sequence_msg;
 mod serial_com;
+mod serial_settings;
+mod song_loop;
+mod srec;
+mod status;
+mod tui;
 mod utils;
 use clap::Parser;
 
 use commands::*;
 
-use serial2_tokio::SerialPort;
-use tokio::sync::{mpsc, Mutex};
 use tauri::Manager;
+use tokio::sync::{mpsc, watch, Mutex};
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(long)]
@@ -21,36 +39,145 @@ struct Args {
     input: Option<String>,
     #[arg(short, long)]
     list: bool,
-    #[arg(short, long, default_value_t = 0)]
-    port: usize,
+    // SRECファイルを検証して内容を表示する
+    #[arg(long, value_name = "SREC")]
+    check_srec: Option<String>,
+    // SRECファイルを書き込む(MIDIの送信より先に行う)
+    // CLIでは実行中のバージョンが分からないため、ダウングレードやバリアント違いは確認しない
+    #[arg(long, value_name = "SREC")]
+    flash: Option<String>,
+    // ローダの起動を待って書き込み、Sequencer ready まで確認する
+    #[arg(long, value_name = "SREC", conflicts_with = "flash")]
+    boot: Option<String>,
+    // ファームウェアの更新を監視して書き込み続ける(-i があれば書き込み後に送り直す)
+    #[arg(long, value_name = "PATH")]
+    watch_firmware: Option<String>,
+    // 更新が落ち着くまで待つ時間(ミリ秒)
+    #[arg(long)]
+    debounce: Option<u64>,
+    // --boot でボードをリセットする制御線
+    #[arg(long, value_enum)]
+    reset: Option<flash::ResetLine>,
+    #[arg(long, value_enum)]
+    flash_mode: Option<flash::FlashMode>,
+    // 変換元のファイル形式(未指定なら自動判定)
+    #[arg(long, value_enum)]
+    firmware_format: Option<firmware::FirmwareFormat>,
+    // バイナリを配置するアドレス(0x... も可)
+    #[arg(long, value_parser = firmware::parse_address)]
+    base_address: Option<u32>,
+    // 生成するSRECの1レコードあたりのデータバイト数
+    #[arg(long)]
+    record_size: Option<usize>,
+    #[arg(long, value_enum)]
+    address_width: Option<firmware::AddressWidth>,
+    // 変換したSRECをファイルに保存する
+    #[arg(long, value_name = "PATH")]
+    save_srec: Option<String>,
+    // Pacedモードの1行ごとの待ち時間(ミリ秒)
+    #[arg(long)]
+    line_delay: Option<u64>,
+    #[arg(short, long)]
+    port: Option<usize>,
     #[arg(long)]
     port_name: Option<String>,
+    // MIQSボードが接続されたポートを自動で選ぶ
+    #[arg(long)]
+    auto: bool,
+    // 送信後(入力なしなら接続のみ)シーケンサの出力を表示し続ける
+    #[arg(short, long)]
+    monitor: bool,
+    // モニタ表示するチャンネル(カンマ区切り、未指定なら全て)
+    #[arg(long, value_delimiter = ',')]
+    channel: Vec<u8>,
+    // モニタ表示に経過時間を付ける
+    #[arg(long)]
+    timestamp: bool,
+    // モニタ表示を色付けする
+    #[arg(long)]
+    color: bool,
+    // ターミナルダッシュボードを表示する
+    #[arg(long)]
+    tui: bool,
+    // イベントを1行1つのJSONで出力する
+    #[arg(long)]
+    json: bool,
+    // シーケンサの応答待ちのタイムアウト(秒)
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+    // 通信設定(未指定ならGUIで保存した設定を使う)
+    #[arg(long)]
+    baud: Option<u32>,
+    #[arg(long)]
+    data_bits: Option<u8>,
+    #[arg(long, value_enum)]
+    parity: Option<serial_settings::Parity>,
+    #[arg(long)]
+    stop_bits: Option<u8>,
+    #[arg(long, value_enum)]
+    flow_control: Option<serial_settings::FlowControl>,
+    #[arg(long)]
+    dtr: Option<bool>,
+    #[arg(long)]
+    rts: Option<bool>,
+    // printf出力の文字コード
+    #[arg(long, value_enum)]
+    printf_encoding: Option<device_printf::PrintfEncoding>,
+    // printfのタグを解析する正規表現
+    #[arg(long)]
+    printf_pattern: Option<String>,
+    // 表示するprintfのレベル(これより詳細なものは隠す)
+    #[arg(long, value_enum)]
+    printf_level: Option<device_printf::DeviceLevel>,
+    // 表示するprintfのモジュール(カンマ区切り)
+    #[arg(long, value_delimiter = ',')]
+    printf_module: Vec<String>,
 }
 // #[derive(Default)]
 struct AppState {
-    inner: Mutex<mpsc::Sender<(InternalCommand, String)>>,
-    srec_file: Mutex<Option<String>>,
-    file_data: Mutex<Option<Vec<u8>>>,
+    // 接続タスクへの要求
+    command_tx: mpsc::Sender<InternalCommand>,
+    // 接続状態(ポート名を含む)
+    status: watch::Sender<status::ConnectionStatus>,
+    config: Mutex<config::AppConfig>,
+    // デバイスのprintf出力(シリアル受信側から同期的に書き込む)
+    printf: std::sync::Mutex<device_printf::PrintfBuffer>,
+    // ボードが報告した実行中のファームウェア
+    firmware: std::sync::Mutex<firmware_info::BoardFirmware>,
+    // 監視中のファームウェアと監視タスク(停止時にabortする)
+    firmware_watch: std::sync::Mutex<Option<(String, tauri::async_runtime::JoinHandle<()>)>>,
+    // MIDIファイルの監視タスク
+    midi_watch: std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    // プレイリストと次の曲を送るタスク
+    playlist: std::sync::Mutex<playlist::Playlist>,
+    playlist_task: std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    // ループ再生の状態と送り直すタスク
+    song_loop: std::sync::Mutex<song_loop::SongLoop>,
+    loop_task: std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    file_data: Mutex<Option<utils::MidiFile>>,
 }
 #[derive(serde::Serialize,Clone)]
 struct ToFrontMsg {
   msg: String,
   id: Option<u16>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  settings: Option<serial_settings::SerialSettings>,
 }
 impl<'a> From<&'a str> for ToFrontMsg {
   fn from(msg: &'a str) -> Self {
     return Self{
       msg: msg.to_string(),
       id: None,
+      settings: None,
     }
   }
 }
 impl ToFrontMsg {
-  fn port_opened() ->Self {
-    return Self{msg: "serial port opened".to_string(), id: Some(1)};
+  fn port_opened(settings: serial_settings::SerialSettings) ->Self {
+    return Self{msg: format!("serial port opened ({settings})"), id: Some(1), settings: Some(settings)};
   }
   fn port_closed() ->Self {
-    return Self { msg: "serial port closed".to_string(), id: Some(2) };
+    return Self { msg: "serial port closed".to_string(), id: Some(2), settings: None };
   }
 }
 // エラーメッセージを格納する構造体
@@ -68,7 +195,6 @@ struct FileInfo {
 
 // アプリケーションのエントリーポイント
 fn main() {
-    const BAUD_RATE: u32 = 115200;
     let args = Args::parse();
     // ignore proxy
     let proxy_env_value = match std::env::var("http_proxy") {
@@ -79,87 +205,131 @@ fn main() {
         }
         Err(_e) => String::from("proxy setting error"),
     };
+    // CLIの出力を崩さないようGUIのときだけ標準エラーにもログを出す
+    let is_cli = args.list
+        || args.check_srec.is_some()
+        || args.disable_gui
+        || args.flash.is_some()
+        || args.boot.is_some()
+        || args.watch_firmware.is_some()
+        || args.monitor
+        || args.tui;
+    let (app_config, config_warning) = config::AppConfig::load_checked();
+    logger::init(app_config.log_filter.as_deref(), !is_cli);
+    if let Some(warning) = config_warning {
+        log::warn!("{warning}");
+    }
     if args.list {
         // Print the list of available ports
-        if let Some(list) = utils::get_serial_port_list() {
-            if list.is_empty() {
-                println!("No serial port found");
-            } else {
-                list.iter().enumerate().for_each(|(i, port)| {
-                    println!("{}: {}", i, port);
-                });
-            }
-        } else {
-            println!("No serial port found");
+        cli::list_ports(args.json);
+    } else if let Some(path) = args.check_srec.as_ref() {
+        if let Err(e) = cli::check_srec(path, &args) {
+            std::process::exit(e.exit_code());
         }
-    } else if args.disable_gui {
+    } else if args.disable_gui
+        || args.flash.is_some()
+        || args.boot.is_some()
+        || args.watch_firmware.is_some()
+        || args.monitor
+        || args.tui
+    {
         // Run CLI Tool
-        tokio::runtime::Builder::new_multi_thread()
+        let result = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .unwrap()
-            .block_on(cli::run(args))
+            .block_on(cli::run(args));
+        if let Err(e) = result {
+            std::process::exit(e.exit_code());
+        }
     } else {
-        let (async_proc_input_tx, async_proc_input_rx) = mpsc::channel(1);
-        let (async_proc_output_tx, mut async_proc_output_rx) = mpsc::channel(1);
+        let window_layout = app_config.window;
+        let mut printf_buffer = device_printf::PrintfBuffer::new(app_config.printf_scrollback());
+        match app_config.printf_parser() {
+            Ok(parser) => printf_buffer.set_parser(parser),
+            Err(e) => log::warn!("invalid printf pattern: {e}"),
+        }
+        let version_parser = app_config.version_options.parser().unwrap_or_else(|e| {
+            log::warn!("invalid version pattern: {e}");
+            firmware_info::VersionParser::default()
+        });
+        let (command_tx, command_rx) = mpsc::channel(8);
         tauri::Builder::default()
             .manage(AppState {
-              inner: Mutex::new(async_proc_input_tx),
-              srec_file: Mutex::new(None),
+              command_tx,
+              status: status::new_status_channel(),
+              config: Mutex::new(app_config),
+              printf: std::sync::Mutex::new(printf_buffer),
+              firmware: std::sync::Mutex::new(firmware_info::BoardFirmware::new(version_parser)),
+              firmware_watch: std::sync::Mutex::new(None),
+              midi_watch: std::sync::Mutex::new(None),
+              playlist: std::sync::Mutex::new(playlist::Playlist::default()),
+              playlist_task: std::sync::Mutex::new(None),
+              song_loop: std::sync::Mutex::new(song_loop::SongLoop::default()),
+              loop_task: std::sync::Mutex::new(None),
               file_data: Mutex::new(None),
             })
-            .setup(|app| {
+            .setup(move |app| {
+              // 前回のウィンドウ位置とサイズを復元
+              if let (Some(window), Some(layout)) = (app.get_window("main"), window_layout) {
+                let _ = window.set_size(tauri::PhysicalSize::new(layout.width, layout.height));
+                let _ = window.set_position(tauri::PhysicalPosition::new(layout.x, layout.y));
+              }
+              let watch_handle = app.handle();
               tauri::async_runtime::spawn(async move {
-                async_process_model(async_proc_input_rx, async_proc_output_tx).await
+                port_watch::watch_ports(watch_handle, port_watch::WATCH_INTERVAL).await
               });
               let app_handle = app.handle();
               tauri::async_runtime::spawn(async move {
-                  loop {
-                      if let Some(output) = async_proc_output_rx.recv().await {
-                        if output.0 == InternalCommand::Open {
-                          if let Ok(mut port) = SerialPort::open(output.1, BAUD_RATE) {
-                            // Todo: フロントへの接続成功通知の実装
-                            println!("Connect Success.");
-                            app_handle.emit_all("message", ToFrontMsg::port_opened()).unwrap();
-                            serial_com::clear_buffer(&mut port);
-                            loop {
-                              tokio::select!(
-                                Some(output) = async_proc_output_rx.recv() => {
-                                  // フロントからのイベント
-                                  if handle_internal_control(output.0,&mut port,&app_handle).await {
-                                    serial_com::clear_buffer(&mut port);
-                                    app_handle.emit_all("message", ToFrontMsg::port_closed()).unwrap();
-                                    break;
-                                  }
-                                }
-                                Ok(v) = serial_com::receive_byte(&mut port) => {
-                                  // Sequencerとの独自プロトコルの通信
-                                  if let Some(sq_msg) = serial_com::receive_sequence_msg(v, &mut port).await {
-                                    handle_sequence_msg(sq_msg, &app_handle);
-                                  }
-                                }
-                              );
-                            }
-                          } else {
-                            // Todo: フロントへの接続失敗通知の実装
-                            println!("faild open port");
-                            app_handle.emit_all("error", ToFrontMsg::from("failed to open Serial Port.")).unwrap();
-                          }
-                        }
-                      }
-                  }
+                connection::connection_task(app_handle, command_rx).await
               });
               Ok(())
             })
+            .on_window_event(|event| {
+              if let tauri::WindowEvent::CloseRequested { .. } = event.event() {
+                save_window_layout(event.window());
+              }
+            })
             .invoke_handler(tauri::generate_handler![
                 read_file,
                 // process_event,
                 send_midi_file, // 本番用
+                send_midi_excerpt,
                 open_file,
                 serialport_open,
                 serialport_close,
                 get_available_serial_ports,
+                detect_boards,
+                get_serial_settings,
+                get_config,
+                update_config,
+                get_reconnect_policy,
+                set_reconnect_policy,
                 send_srec_file, // srec fileの転送
+                flash_firmware,
+                export_logs,
+                inspect_srec_file,
+                save_converted_srec,
+                start_firmware_watch,
+                stop_firmware_watch,
+                set_midi_live_reload,
+                get_playlist,
+                playlist_add,
+                playlist_remove,
+                playlist_move,
+                playlist_shuffle,
+                playlist_clear,
+                playlist_play,
+                playlist_stop,
+                set_playlist_options,
+                import_playlist,
+                export_playlist,
+                get_loop,
+                start_loop,
+                stop_loop,
+                get_printf_log,
+                clear_printf_log,
+                get_connection_status,
             ])
             .run(tauri::generate_context!())
             .expect("error while running tauri application");
@@ -171,14 +341,20 @@ fn main() {
     }
 }
 
-// Asyncの世界とのやり取り
-async fn async_process_model(
-    mut input_rx: mpsc::Receiver<(InternalCommand, String)>,
-    output_tx: mpsc::Sender<(InternalCommand, String)>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    while let Some(input) = input_rx.recv().await {
-        let output = input;
-        output_tx.send(output).await?;
+// 終了時のウィンドウ位置とサイズを保存
+fn save_window_layout(window: &tauri::Window) {
+    let (Ok(size), Ok(position)) = (window.outer_size(), window.outer_position()) else {
+        return;
+    };
+    let state = window.state::<AppState>();
+    let mut config = tauri::async_runtime::block_on(state.config.lock());
+    config.window = Some(config::WindowLayout {
+        width: size.width,
+        height: size.height,
+        x: position.x,
+        y: position.y,
+    });
+    if let Err(e) = config.save() {
+        log::warn!("Failed to save config: {e}");
     }
-    Ok(())
 }
@@ -0,0 +1,675 @@
+// SMFの一部(小節/拍または時間の範囲)を切り出す
+// 開始位置までのテンポ、拍子、プログラムチェンジ、コントローラ、ピッチベンドを先頭に置き、
+// 開始位置で鳴っている音は打ち直す
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// 4分音符あたりのマイクロ秒(テンポ指定がない場合は120BPM)
+const DEFAULT_TEMPO: u32 = 500_000;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+const META_KEY_SIGNATURE: u8 = 0x59;
+// 開始位置より前でも残すメタイベント(トラック名、楽器名、チャンネル/ポート指定)
+const SETUP_META: [u8; 4] = [0x03, 0x04, 0x20, 0x21];
+// RPN/NRPNとデータエントリ(順序に意味があるので最後の値ではなく全て残す)
+const PARAMETER_CC: [u8; 8] = [6, 38, 96, 97, 98, 99, 100, 101];
+const ALL_SOUND_OFF: u8 = 120;
+const RESET_ALL_CONTROLLERS: u8 = 121;
+const ALL_NOTES_OFF: u8 = 123;
+
+// 切り出し位置 "80" / "80.3" (小節.拍、1始まり) または "83.5s" / "1:23.5" (時間)
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Position {
+    Bar { bar: u32, beat: u32 },
+    Time { seconds: f64 },
+}
+impl FromStr for Position {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid position: {s} (e.g. 80, 80.3, 83.5s, 1:23.5)");
+        let seconds = if let Some(seconds) = s.strip_suffix('s') {
+            seconds.parse::<f64>().map_err(|_| invalid())?
+        } else if let Some((minutes, seconds)) = s.split_once(':') {
+            let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+            minutes as f64 * 60.0 + seconds.parse::<f64>().map_err(|_| invalid())?
+        } else {
+            let (bar, beat) = s.split_once('.').unwrap_or((s, "1"));
+            let bar = bar.parse::<u32>().map_err(|_| invalid())?;
+            let beat = beat.parse::<u32>().map_err(|_| invalid())?;
+            if bar == 0 || beat == 0 {
+                return Err(format!("bars and beats start at 1: {s}"));
+            }
+            return Ok(Self::Bar { bar, beat });
+        };
+        if !seconds.is_finite() || seconds < 0.0 {
+            return Err(invalid());
+        }
+        Ok(Self::Time { seconds })
+    }
+}
+impl TryFrom<String> for Position {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bar { bar, beat } => write!(f, "bar {bar}.{beat}"),
+            Self::Time { seconds } => write!(f, "{seconds}s"),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ExcerptRange {
+    pub start: Position,
+    // 未指定なら曲の最後まで(指定位置は含まない)
+    pub end: Option<Position>,
+}
+impl std::fmt::Display for ExcerptRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.end {
+            Some(end) => write!(f, "{} - {end}", self.start),
+            None => write!(f, "{} - end", self.start),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum EventKind {
+    // ステータスとデータ(1または2バイト)
+    Channel { status: u8, data: Vec<u8> },
+    // F0 または F7
+    SysEx { status: u8, data: Vec<u8> },
+    Meta { kind: u8, data: Vec<u8> },
+}
+
+#[derive(Clone, Debug)]
+struct Event {
+    tick: u64,
+    kind: EventKind,
+}
+impl Event {
+    fn channel(tick: u64, status: u8, data: &[u8]) -> Self {
+        Self {
+            tick,
+            kind: EventKind::Channel {
+                status,
+                data: data.to_vec(),
+            },
+        }
+    }
+    fn meta(tick: u64, kind: u8, data: &[u8]) -> Self {
+        Self {
+            tick,
+            kind: EventKind::Meta {
+                kind,
+                data: data.to_vec(),
+            },
+        }
+    }
+    fn is_note_off(&self) -> bool {
+        match &self.kind {
+            EventKind::Channel { status, data } => {
+                status & 0xF0 == 0x80 || (status & 0xF0 == 0x90 && data[1] == 0)
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or("unexpected end of MIDI file")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    // 可変長数値(最大4バイト)
+    fn var_len(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = (value << 7) | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("invalid variable-length quantity".to_string())
+    }
+}
+
+struct Smf {
+    format: u16,
+    // 4分音符あたりのティック数
+    division: u16,
+    tracks: Vec<Vec<Event>>,
+}
+impl Smf {
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data);
+        if reader.bytes(4)? != b"MThd" {
+            return Err("not a Standard MIDI File".to_string());
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            return Err("invalid MIDI header".to_string());
+        }
+        let format = reader.u16()?;
+        let _track_count = reader.u16()?;
+        let division = reader.u16()?;
+        reader.bytes(header_len - 6)?;
+        if division & 0x8000 != 0 {
+            return Err("SMPTE time division is not supported".to_string());
+        }
+        if division == 0 {
+            return Err("invalid time division: 0".to_string());
+        }
+        let mut tracks = Vec::new();
+        while !reader.is_empty() {
+            let id = reader.bytes(4)?;
+            let len = reader.u32()? as usize;
+            let chunk = reader.bytes(len)?;
+            // 未知のチャンクは読み飛ばす
+            if id == b"MTrk" {
+                let track =
+                    parse_track(chunk).map_err(|e| format!("track {}: {e}", tracks.len()))?;
+                tracks.push(track);
+            }
+        }
+        Ok(Self {
+            format,
+            division,
+            tracks,
+        })
+    }
+    // 全トラックのメタイベント (ティック, データ) をティック順に
+    fn meta_events(&self, meta: u8) -> Vec<(u64, &[u8])> {
+        let mut events = self
+            .tracks
+            .iter()
+            .flatten()
+            .filter_map(|e| match &e.kind {
+                EventKind::Meta { kind, data } if *kind == meta => Some((e.tick, data.as_slice())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(tick, _)| *tick);
+        events
+    }
+    fn length(&self) -> u64 {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.last())
+            .map(|e| e.tick)
+            .max()
+            .unwrap_or(0)
+    }
+    fn tick_at(&self, position: Position) -> Result<u64, String> {
+        match position {
+            Position::Bar { bar, beat } => self.bar_to_tick(bar, beat),
+            Position::Time { seconds } => Ok(self.seconds_to_tick(seconds)),
+        }
+    }
+    // 拍子の変更は小節の頭にあるものとして数える
+    fn bar_to_tick(&self, bar: u32, beat: u32) -> Result<u64, String> {
+        let division = self.division as u64;
+        let bar_ticks =
+            |numerator: u64, denominator: u32| ((division * 4 * numerator) >> denominator).max(1);
+        let (mut numerator, mut denominator) = (4u64, 2u32);
+        let (mut segment_tick, mut segment_bar) = (0u64, 1u64);
+        for (tick, data) in self.meta_events(META_TIME_SIGNATURE) {
+            if data.len() < 2 {
+                continue;
+            }
+            let bars = (tick - segment_tick).div_ceil(bar_ticks(numerator, denominator));
+            if (bar as u64) < segment_bar + bars {
+                break;
+            }
+            segment_tick = tick;
+            segment_bar += bars;
+            numerator = data[0].max(1) as u64;
+            denominator = data[1].min(6) as u32;
+        }
+        if beat as u64 > numerator {
+            return Err(format!(
+                "bar {bar} has only {numerator} beats ({numerator}/{})",
+                1 << denominator
+            ));
+        }
+        let beat_ticks = ((division * 4) >> denominator).max(1);
+        Ok(segment_tick
+            + (bar as u64 - segment_bar) * bar_ticks(numerator, denominator)
+            + (beat as u64 - 1) * beat_ticks)
+    }
+    fn seconds_to_tick(&self, seconds: f64) -> u64 {
+        let division = self.division as f64;
+        let target = seconds * 1_000_000.0;
+        let (mut tick, mut usec, mut tempo) = (0u64, 0f64, DEFAULT_TEMPO as f64);
+        for (next_tick, data) in self.meta_events(META_TEMPO) {
+            if data.len() < 3 {
+                continue;
+            }
+            let segment = (next_tick - tick) as f64 * tempo / division;
+            if usec + segment > target {
+                break;
+            }
+            usec += segment;
+            tick = next_tick;
+            tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]).max(1) as f64;
+        }
+        tick + ((target - usec) * division / tempo) as u64
+    }
+}
+
+fn parse_track(data: &[u8]) -> Result<Vec<Event>, String> {
+    let mut reader = Reader::new(data);
+    let mut events = Vec::new();
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while !reader.is_empty() {
+        tick += reader.var_len()? as u64;
+        let first = reader.u8()?;
+        let kind = match first {
+            0xFF => {
+                let kind = reader.u8()?;
+                let len = reader.var_len()? as usize;
+                let data = reader.bytes(len)?.to_vec();
+                running_status = None;
+                if kind == META_END_OF_TRACK {
+                    break;
+                }
+                EventKind::Meta { kind, data }
+            }
+            0xF0 | 0xF7 => {
+                let len = reader.var_len()? as usize;
+                running_status = None;
+                EventKind::SysEx {
+                    status: first,
+                    data: reader.bytes(len)?.to_vec(),
+                }
+            }
+            0xF1..=0xFE => return Err(format!("unsupported status: {first:#04X}")),
+            _ => {
+                // ランニングステータスなら first はデータの1バイト目
+                let (status, first) = if first & 0x80 != 0 {
+                    running_status = Some(first);
+                    (first, reader.u8()?)
+                } else {
+                    (running_status.ok_or("data byte without status")?, first)
+                };
+                let mut data = vec![first];
+                if !matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    data.push(reader.u8()?);
+                }
+                EventKind::Channel { status, data }
+            }
+        };
+        events.push(Event { tick, kind });
+    }
+    Ok(events)
+}
+
+// 開始位置でのチャンネルの状態
+#[derive(Default)]
+struct ChannelState {
+    controllers: BTreeMap<u8, u8>,
+    parameters: Vec<[u8; 2]>,
+    program: Option<u8>,
+    pressure: Option<u8>,
+    pitch_bend: Option<[u8; 2]>,
+    // 鳴っている音 (ノート番号 → ベロシティ)
+    notes: BTreeMap<u8, u8>,
+}
+impl ChannelState {
+    fn apply(&mut self, status: u8, data: &[u8]) {
+        match (status & 0xF0, data) {
+            (0x80, [note, ..]) | (0x90, [note, 0]) => {
+                self.notes.remove(note);
+            }
+            (0x90, [note, velocity]) => {
+                self.notes.insert(*note, *velocity);
+            }
+            (0xB0, [cc, value]) if PARAMETER_CC.contains(cc) => self.parameters.push([*cc, *value]),
+            (0xB0, [RESET_ALL_CONTROLLERS, _]) => {
+                self.controllers.clear();
+                self.pressure = None;
+                self.pitch_bend = None;
+            }
+            // All Sound Off / All Notes Off とモード切り替え(いずれも発音中の音を止める)
+            (0xB0, [cc, _]) if *cc == ALL_SOUND_OFF || *cc >= ALL_NOTES_OFF => self.notes.clear(),
+            (0xB0, [cc, _]) if *cc >= 120 => {}
+            (0xB0, [cc, value]) => {
+                self.controllers.insert(*cc, *value);
+            }
+            (0xC0, [program]) => self.program = Some(*program),
+            (0xD0, [pressure]) => self.pressure = Some(*pressure),
+            (0xE0, [lsb, msb]) => self.pitch_bend = Some([*lsb, *msb]),
+            _ => {}
+        }
+    }
+    // バンクセレクトを含むコントローラを先に置いてからプログラムチェンジ
+    fn events(&self, channel: u8) -> Vec<Event> {
+        let controllers = self
+            .controllers
+            .iter()
+            .map(|(cc, value)| [*cc, *value])
+            .chain(self.parameters.iter().copied())
+            .map(|data| Event::channel(0, 0xB0 | channel, &data));
+        let program = self
+            .program
+            .map(|p| Event::channel(0, 0xC0 | channel, &[p]));
+        let pressure = self
+            .pressure
+            .map(|p| Event::channel(0, 0xD0 | channel, &[p]));
+        let pitch_bend = self
+            .pitch_bend
+            .map(|b| Event::channel(0, 0xE0 | channel, &b));
+        let notes = self
+            .notes
+            .iter()
+            .map(|(note, velocity)| Event::channel(0, 0x90 | channel, &[*note, *velocity]));
+        controllers
+            .chain(program)
+            .chain(pressure)
+            .chain(pitch_bend)
+            .chain(notes)
+            .collect()
+    }
+}
+
+// start 以上 end 未満のイベントを start を0として切り出す
+fn cut_track(events: &[Event], start: u64, end: Option<u64>) -> Vec<Event> {
+    let mut setup = Vec::new();
+    let mut latest_meta = BTreeMap::new();
+    let mut channels: [ChannelState; 16] = Default::default();
+    // 開始位置ちょうどのノートオフは開始前の状態として扱う(打ち直さない)
+    let (before, body): (Vec<_>, Vec<_>) = events
+        .iter()
+        .partition(|e| e.tick < start || (e.tick == start && e.is_note_off()));
+    for event in before {
+        match &event.kind {
+            EventKind::Channel { status, data } => {
+                channels[(status & 0x0F) as usize].apply(*status, data)
+            }
+            EventKind::SysEx { .. } => setup.push(Event {
+                tick: 0,
+                ..event.clone()
+            }),
+            EventKind::Meta { kind, data } if SETUP_META.contains(kind) => {
+                setup.push(Event::meta(0, *kind, data))
+            }
+            EventKind::Meta { kind, data }
+                if matches!(*kind, META_TEMPO | META_TIME_SIGNATURE | META_KEY_SIGNATURE) =>
+            {
+                latest_meta.insert(*kind, data);
+            }
+            EventKind::Meta { .. } => {}
+        }
+    }
+    let mut output = setup;
+    output.extend(
+        latest_meta
+            .iter()
+            .map(|(kind, data)| Event::meta(0, *kind, data)),
+    );
+    // 切り出し範囲の終わりで止める音 (チャンネル, ノート番号)
+    let mut sounding = std::collections::BTreeSet::new();
+    for (channel, state) in channels.iter().enumerate() {
+        output.extend(state.events(channel as u8));
+        sounding.extend(state.notes.keys().map(|note| (channel as u8, *note)));
+    }
+    for event in body {
+        if end.is_some_and(|end| event.tick >= end) {
+            break;
+        }
+        if let EventKind::Channel { status, data } = &event.kind {
+            let key = (status & 0x0F, data[0]);
+            if event.is_note_off() {
+                sounding.remove(&key);
+            } else if status & 0xF0 == 0x90 {
+                sounding.insert(key);
+            }
+        }
+        output.push(Event {
+            tick: event.tick - start,
+            ..event.clone()
+        });
+    }
+    let last = output.last().map_or(0, |e| e.tick);
+    let end_tick = end.map_or(last, |end| (end - start).max(last));
+    if end.is_some() {
+        output.extend(
+            sounding
+                .iter()
+                .map(|(channel, note)| Event::channel(end_tick, 0x80 | channel, &[*note, 0x40])),
+        );
+    }
+    output.push(Event::meta(end_tick, META_END_OF_TRACK, &[]));
+    output
+}
+
+fn write_var_len(out: &mut Vec<u8>, value: u64) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        groups.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(groups.iter().rev());
+}
+
+// ランニングステータスを使わずに書き出す
+fn write_smf(format: u16, division: u16, tracks: &[Vec<Event>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend(b"MThd");
+    out.extend(6u32.to_be_bytes());
+    out.extend(format.to_be_bytes());
+    out.extend((tracks.len() as u16).to_be_bytes());
+    out.extend(division.to_be_bytes());
+    for track in tracks {
+        let mut body = Vec::new();
+        let mut last = 0;
+        for event in track {
+            write_var_len(&mut body, event.tick - last);
+            last = event.tick;
+            match &event.kind {
+                EventKind::Channel { status, data } => {
+                    body.push(*status);
+                    body.extend(data);
+                }
+                EventKind::SysEx { status, data } => {
+                    body.push(*status);
+                    write_var_len(&mut body, data.len() as u64);
+                    body.extend(data);
+                }
+                EventKind::Meta { kind, data } => {
+                    body.extend([0xFF, *kind]);
+                    write_var_len(&mut body, data.len() as u64);
+                    body.extend(data);
+                }
+            }
+        }
+        out.extend(b"MTrk");
+        out.extend((body.len() as u32).to_be_bytes());
+        out.extend(body);
+    }
+    out
+}
+
+pub fn excerpt(data: &[u8], range: &ExcerptRange) -> Result<Vec<u8>, String> {
+    let smf = Smf::parse(data)?;
+    let start = smf.tick_at(range.start)?;
+    let end = range.end.map(|end| smf.tick_at(end)).transpose()?;
+    if end.is_some_and(|end| end <= start) {
+        return Err(format!(
+            "end position must be after the start position ({range})"
+        ));
+    }
+    if start >= smf.length() {
+        return Err(format!("{} is after the end of the song", range.start));
+    }
+    let tracks = smf
+        .tracks
+        .iter()
+        .map(|track| cut_track(track, start, end))
+        .collect::<Vec<_>>();
+    Ok(write_smf(smf.format, smf.division, &tracks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::check_midi_format;
+
+    const TEMPO_120: [u8; 3] = [0x07, 0xA1, 0x20];
+    const TEMPO_100: [u8; 3] = [0x09, 0x27, 0xC0];
+
+    // 4/4、480ティック/4分音符の3小節
+    fn song() -> Vec<u8> {
+        let track = vec![
+            Event::meta(0, META_TEMPO, &TEMPO_120),
+            Event::meta(0, META_TIME_SIGNATURE, &[4, 2, 24, 8]),
+            Event::channel(0, 0xC0, &[5]),
+            Event::channel(0, 0xB0, &[7, 100]),
+            Event::channel(0, 0x90, &[60, 100]),
+            Event::channel(1200, 0x80, &[60, 64]),
+            Event::meta(1920, META_TEMPO, &TEMPO_100),
+            Event::channel(1920, 0x90, &[62, 100]),
+            Event::channel(2400, 0x80, &[62, 64]),
+            Event::channel(3840, 0x90, &[64, 100]),
+            Event::channel(4320, 0x80, &[64, 64]),
+            Event::meta(4320, META_END_OF_TRACK, &[]),
+        ];
+        write_smf(0, 480, &[track])
+    }
+
+    fn range(start: &str, end: Option<&str>) -> ExcerptRange {
+        ExcerptRange {
+            start: start.parse().unwrap(),
+            end: end.map(|end| end.parse().unwrap()),
+        }
+    }
+
+    // 切り出した結果をもう一度読んで1トラック目を返す
+    fn cut(start: &str, end: Option<&str>) -> Vec<Event> {
+        let data = excerpt(&song(), &range(start, end)).unwrap();
+        assert!(check_midi_format(&data));
+        let mut smf = Smf::parse(&data).unwrap();
+        assert_eq!(smf.division, 480);
+        smf.tracks.remove(0)
+    }
+
+    // (ティック, ステータス, ノート番号)
+    fn notes(track: &[Event]) -> Vec<(u64, u8, u8)> {
+        track
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Channel { status, data } if matches!(status & 0xF0, 0x80 | 0x90) => {
+                    Some((e.tick, *status, data[0]))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn tempos(track: &[Event]) -> Vec<(u64, Vec<u8>)> {
+        track
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Meta { kind, data } if *kind == META_TEMPO => {
+                    Some((e.tick, data.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn has_channel_event(track: &[Event], status: u8, data: &[u8]) -> bool {
+        track.iter().any(|e| {
+            e.tick == 0
+                && matches!(&e.kind, EventKind::Channel { status: s, data: d } if *s == status && d == data)
+        })
+    }
+
+    #[test]
+    fn parse_positions() {
+        assert_eq!("80".parse(), Ok(Position::Bar { bar: 80, beat: 1 }));
+        assert_eq!("80.3".parse(), Ok(Position::Bar { bar: 80, beat: 3 }));
+        assert_eq!("83.5s".parse(), Ok(Position::Time { seconds: 83.5 }));
+        assert_eq!("1:23.5".parse(), Ok(Position::Time { seconds: 83.5 }));
+        assert!("0".parse::<Position>().is_err());
+        assert!("-1s".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn cut_by_bars() {
+        let track = cut("2", Some("3"));
+        assert_eq!(notes(&track), vec![(0, 0x90, 62), (480, 0x80, 62)]);
+        // 開始位置までの状態を先頭に置く
+        assert!(has_channel_event(&track, 0xC0, &[5]));
+        assert!(has_channel_event(&track, 0xB0, &[7, 100]));
+        // 3小節目のイベントは含まない
+        assert!(track.iter().all(|e| e.tick < 1920));
+    }
+
+    #[test]
+    fn carries_tempo_before_start() {
+        let track = cut("3", None);
+        assert_eq!(tempos(&track), vec![(0, TEMPO_100.to_vec())]);
+        assert_eq!(notes(&track), vec![(0, 0x90, 64), (480, 0x80, 64)]);
+    }
+
+    #[test]
+    fn cut_by_time_retriggers_held_notes() {
+        // 120BPMで1秒 = 960ティック(鳴っている音を打ち直す)
+        let track = cut("1s", Some("1.4"));
+        assert_eq!(notes(&track), vec![(0, 0x90, 60), (240, 0x80, 60)]);
+        assert_eq!(tempos(&track), vec![(0, TEMPO_120.to_vec())]);
+    }
+
+    #[test]
+    fn stops_notes_at_the_end() {
+        let track = cut("1.2", Some("1.3"));
+        assert_eq!(notes(&track), vec![(0, 0x90, 60), (480, 0x80, 60)]);
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert!(excerpt(&song(), &range("10", None))
+            .unwrap_err()
+            .contains("after the end"));
+        assert!(excerpt(&song(), &range("600s", None)).is_err());
+        assert!(excerpt(&song(), &range("2", Some("2"))).is_err());
+        assert!(excerpt(&song(), &range("1.5", None)).is_err());
+        assert!(excerpt(b"MThd", &range("1", None)).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// 読み込み済みのMIDIファイルの更新を監視し、ボードが空いていれば送り直す
+use crate::commands::{self, InternalCommand};
+use crate::file_watch::{FileWatcher, DEFAULT_DEBOUNCE_MS};
+use crate::status::ConnectionState;
+use crate::utils::{check_midi_format, MidiFile};
+use crate::AppState;
+use std::time::Duration;
+use tauri::Manager;
+
+#[derive(serde::Serialize, Clone)]
+struct ReloadEvent<'a> {
+    path: &'a str,
+    // false なら読み込んだだけ(ボードが空くのを待っている)
+    sent: bool,
+    message: Option<String>,
+}
+
+fn notify<R: tauri::Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    path: &str,
+    sent: bool,
+    message: Option<String>,
+) {
+    match &message {
+        Some(message) => log::warn!("midi reload ({path}): {message}"),
+        None => log::info!("midi reload ({path}): sent={sent}"),
+    }
+    let _ = app_handle.emit_all(
+        "midi-reloaded",
+        ReloadEvent {
+            path,
+            sent,
+            message,
+        },
+    );
+}
+
+// 監視対象を切り替える(None なら停止)
+pub fn restart<R: tauri::Runtime>(app_handle: &tauri::AppHandle<R>, path: Option<String>) {
+    let state = app_handle.state::<AppState>();
+    let handle = path.map(|path| tauri::async_runtime::spawn(watch_midi(app_handle.clone(), path)));
+    let old = std::mem::replace(&mut *state.midi_watch.lock().unwrap(), handle);
+    if let Some(old) = old {
+        old.abort();
+    }
+}
+
+async fn watch_midi<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>, path: String) {
+    let state = app_handle.state::<AppState>();
+    let mut watcher = FileWatcher::new(&path, Duration::from_millis(DEFAULT_DEBOUNCE_MS));
+    let mut status_rx = state.status.subscribe();
+    // 読み込んだが、まだ送っていない
+    let mut pending = false;
+    log::info!("watching midi file: {path}");
+    loop {
+        tokio::select!(
+            _ = watcher.wait_for_change() => {
+                match reload(&state, &path).await {
+                    Ok(_) => {
+                        pending = true;
+                        if status_rx.borrow().state != ConnectionState::ConnectedIdle {
+                            notify(&app_handle, &path, false, None);
+                        }
+                    }
+                    Err(e) => {
+                        notify(&app_handle, &path, false, Some(e));
+                        continue;
+                    }
+                }
+            }
+            result = status_rx.changed(), if pending => {
+                if result.is_err() {
+                    break;
+                }
+            }
+        );
+        if !pending {
+            continue;
+        }
+        // 演奏中や書き込み中は終わるまで待つ
+        if status_rx.borrow_and_update().state != ConnectionState::ConnectedIdle {
+            continue;
+        }
+        pending = false;
+        // 待っている間に別の曲を読み込んでいたら送らない
+        let data = state
+            .file_data
+            .lock()
+            .await
+            .as_ref()
+            .filter(|file| file.path == path)
+            .map(|file| file.data.clone());
+        let Some(data) = data else {
+            continue;
+        };
+        match commands::request(&state, |reply| InternalCommand::Send { data, reply }).await {
+            Ok(_) => notify(&app_handle, &path, true, None),
+            Err(e) => notify(&app_handle, &path, false, Some(e)),
+        }
+    }
+}
+
+// 形式を確認してから読み込み済みのデータを置き換える
+async fn reload(state: &AppState, path: &str) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    if !check_midi_format(&data) {
+        return Err(format!(
+            "Invalid File Format: {path} is not Standard MIDI Format."
+        ));
+    }
+    *state.file_data.lock().await = Some(MidiFile {
+        path: path.to_string(),
+        data,
+    });
+    Ok(())
+}
@@ -0,0 +1,333 @@
+// MIDIファイルのプレイリスト(シーケンサの End を受け取ったら次の曲を送る)
+use crate::commands::{self, InternalCommand};
+use crate::utils::{check_midi_format, MidiFile};
+use crate::AppState;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PlaylistOptions {
+    pub repeat: RepeatMode,
+    // 曲間の待ち時間(ミリ秒)
+    pub gap_ms: u64,
+}
+
+#[derive(Default)]
+pub struct Playlist {
+    entries: Vec<String>,
+    current: Option<usize>,
+    // 連続再生中か(End で次の曲へ進む)
+    playing: bool,
+}
+
+// フロントへ通知する内容
+#[derive(serde::Serialize, Clone)]
+pub struct PlaylistState {
+    entries: Vec<String>,
+    current: Option<usize>,
+    next: Option<usize>,
+    playing: bool,
+    options: PlaylistOptions,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct PlaylistEvent {
+    #[serde(flatten)]
+    state: PlaylistState,
+    message: Option<String>,
+}
+
+impl Playlist {
+    pub fn add(&mut self, paths: impl IntoIterator<Item = String>) {
+        self.entries.extend(paths);
+    }
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        self.check_index(index)?;
+        self.entries.remove(index);
+        self.current = match self.current {
+            Some(current) if current == index => None,
+            Some(current) if current > index => Some(current - 1),
+            current => current,
+        };
+        Ok(())
+    }
+    // 曲を from から to へ移動する(再生中の曲の位置も追従する)
+    pub fn move_entry(&mut self, from: usize, to: usize) -> Result<(), String> {
+        self.check_index(from)?;
+        self.check_index(to)?;
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        self.current = self.current.map(|current| {
+            if current == from {
+                to
+            } else if from < current && current <= to {
+                current - 1
+            } else if to <= current && current < from {
+                current + 1
+            } else {
+                current
+            }
+        });
+        Ok(())
+    }
+    pub fn shuffle(&mut self) {
+        let mut order = (0..self.entries.len()).collect::<Vec<_>>();
+        let mut rng = XorShift::from_time();
+        // Fisher-Yates
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.next() as usize % (i + 1));
+        }
+        self.current = self
+            .current
+            .and_then(|current| order.iter().position(|&i| i == current));
+        self.entries = order.iter().map(|&i| self.entries[i].clone()).collect();
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current = None;
+        self.playing = false;
+    }
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+    // 繰り返しの設定に従って次に送る曲
+    pub fn next_index(&self, repeat: RepeatMode) -> Option<usize> {
+        let Some(current) = self.current else {
+            return (!self.entries.is_empty()).then_some(0);
+        };
+        match repeat {
+            RepeatMode::One if current < self.entries.len() => Some(current),
+            _ if current + 1 < self.entries.len() => Some(current + 1),
+            RepeatMode::All if !self.entries.is_empty() => Some(0),
+            _ => None,
+        }
+    }
+    pub fn state(&self, options: PlaylistOptions) -> PlaylistState {
+        PlaylistState {
+            entries: self.entries.clone(),
+            current: self.current,
+            next: if self.playing {
+                self.next_index(options.repeat)
+            } else {
+                None
+            },
+            playing: self.playing,
+            options,
+        }
+    }
+    fn check_index(&self, index: usize) -> Result<(), String> {
+        if index < self.entries.len() {
+            Ok(())
+        } else {
+            Err(format!("playlist index {index} is out of range"))
+        }
+    }
+}
+
+// シャッフル用の簡易な乱数(再現性は不要)
+struct XorShift(u64);
+impl XorShift {
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self(nanos | 1)
+    }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+pub async fn notify<R: tauri::Runtime>(manager: &impl Manager<R>, message: Option<String>) {
+    let state = manager.state::<AppState>();
+    let options = state.config.lock().await.playlist.clone();
+    let playlist_state = state.playlist.lock().unwrap().state(options);
+    if let Some(message) = &message {
+        log::warn!("playlist: {message}");
+    }
+    let _ = manager.emit_all(
+        "playlist-changed",
+        PlaylistEvent {
+            state: playlist_state,
+            message,
+        },
+    );
+}
+
+// index の曲から連続再生を始める(None なら現在の曲か先頭から)
+pub fn start<R: tauri::Runtime>(
+    manager: &impl Manager<R>,
+    index: Option<usize>,
+) -> Result<(), String> {
+    let state = manager.state::<AppState>();
+    let index = {
+        let mut playlist = state.playlist.lock().unwrap();
+        let index = index.or(playlist.current).unwrap_or(0);
+        playlist.check_index(index)?;
+        playlist.playing = true;
+        index
+    };
+    spawn(manager, play(manager.app_handle(), index));
+    Ok(())
+}
+
+// 連続再生をやめる(演奏中の曲はそのまま)
+pub fn stop<R: tauri::Runtime>(manager: &impl Manager<R>) {
+    let state = manager.state::<AppState>();
+    state.playlist.lock().unwrap().playing = false;
+    if let Some(task) = state.playlist_task.lock().unwrap().take() {
+        task.abort();
+    }
+}
+
+// シーケンサから End を受け取ったときに呼ぶ
+pub fn on_end<R: tauri::Runtime>(manager: &impl Manager<R>) {
+    if manager.state::<AppState>().playlist.lock().unwrap().playing {
+        spawn(manager, advance(manager.app_handle()));
+    }
+}
+
+fn spawn<R: tauri::Runtime>(
+    manager: &impl Manager<R>,
+    task: impl std::future::Future<Output = ()> + Send + 'static,
+) {
+    let handle = tauri::async_runtime::spawn(task);
+    let old = manager
+        .state::<AppState>()
+        .playlist_task
+        .lock()
+        .unwrap()
+        .replace(handle);
+    if let Some(old) = old {
+        old.abort();
+    }
+}
+
+// 曲間の待ち時間をおいて次の曲を送る
+async fn advance<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    let options = state.config.lock().await.playlist.clone();
+    let next = state.playlist.lock().unwrap().next_index(options.repeat);
+    let Some(next) = next else {
+        state.playlist.lock().unwrap().playing = false;
+        log::info!("playlist finished");
+        notify(&app_handle, None).await;
+        return;
+    };
+    tokio::time::sleep(Duration::from_millis(options.gap_ms)).await;
+    play(app_handle.clone(), next).await;
+}
+
+// 読めない曲は飛ばして次を試す(一周したらやめる)
+async fn play<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>, index: usize) {
+    let state = app_handle.state::<AppState>();
+    let repeat = state.config.lock().await.playlist.repeat;
+    let mut index = Some(index);
+    let tries = state.playlist.lock().unwrap().entries.len();
+    for _ in 0..tries {
+        let Some(current) = index else {
+            break;
+        };
+        let path = {
+            let mut playlist = state.playlist.lock().unwrap();
+            let Some(path) = playlist.entries.get(current).cloned() else {
+                break;
+            };
+            playlist.current = Some(current);
+            path
+        };
+        let data = match read_midi(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                notify(&app_handle, Some(e)).await;
+                // 同じ曲の繰り返しでも飛ばす
+                let repeat = match repeat {
+                    RepeatMode::One => RepeatMode::Off,
+                    repeat => repeat,
+                };
+                index = state.playlist.lock().unwrap().next_index(repeat);
+                continue;
+            }
+        };
+        log::info!("playlist: send {path}");
+        // 読み込み済みの曲として扱う(ライブリロードや送信ボタンもこの曲を使う)
+        *state.file_data.lock().await = Some(MidiFile {
+            path: path.clone(),
+            data: data.clone(),
+        });
+        if state.config.lock().await.midi_live_reload {
+            crate::midi_reload::restart(&app_handle, Some(path.clone()));
+        }
+        notify(&app_handle, None).await;
+        let result = commands::request(&state, |reply| InternalCommand::Send { data, reply }).await;
+        if let Err(e) = result {
+            // 接続の問題は次の曲でも失敗するので止める
+            state.playlist.lock().unwrap().playing = false;
+            notify(&app_handle, Some(e)).await;
+        }
+        return;
+    }
+    state.playlist.lock().unwrap().playing = false;
+    notify(
+        &app_handle,
+        Some("no playable file in the playlist".to_string()),
+    )
+    .await;
+}
+
+fn read_midi(path: &str) -> Result<Vec<u8>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    if !check_midi_format(&data) {
+        return Err(format!(
+            "Invalid File Format: {path} is not Standard MIDI Format."
+        ));
+    }
+    Ok(data)
+}
+
+// M3U/M3U8 を読む(相対パスはプレイリストのあるディレクトリから解決する)
+pub fn import_m3u(path: &str) -> Result<Vec<String>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes);
+    // UTF-8 でなければ Shift_JIS とみなす
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::SHIFT_JIS
+            .decode_without_bom_handling(bytes)
+            .0
+            .into_owned(),
+    };
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line).to_string_lossy().into_owned())
+        .collect())
+}
+
+// 拡張M3U形式(UTF-8)で書き出す
+pub fn export_m3u(path: &str, entries: &[String]) -> Result<(), String> {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let title = Path::new(entry)
+            .file_stem()
+            .map_or(entry.clone(), |stem| stem.to_string_lossy().into_owned());
+        text.push_str(&format!("#EXTINF:-1,{title}\n{entry}\n"));
+    }
+    std::fs::write(path, text).map_err(|e| format!("{path}: {e}"))
+}
@@ -0,0 +1,52 @@
+// シリアルポートの抜き差しを監視してフロントへ通知する
+use crate::{commands::InternalCommand, utils::PortInfo, AppState};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tauri::Manager;
+
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn port_map() -> BTreeMap<String, PortInfo> {
+    crate::utils::get_serial_port_info()
+        .into_iter()
+        .map(|info| (info.name.clone(), info))
+        .collect()
+}
+
+pub async fn watch_ports(app_handle: tauri::AppHandle, interval: Duration) {
+    let mut known = port_map();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let current = port_map();
+        if current.keys().eq(known.keys()) {
+            continue;
+        }
+        let state = app_handle.state::<AppState>();
+        let open_port = state.status.borrow().port.clone();
+        for (name, info) in current.iter() {
+            if !known.contains_key(name) {
+                let _ = app_handle.emit_all("port-added", info.clone());
+            }
+        }
+        for (name, info) in known.iter() {
+            if current.contains_key(name) {
+                continue;
+            }
+            // 抜かれたポートは最後に取得できた情報で通知する
+            let mut info = info.clone();
+            info.in_use = open_port.as_deref() == Some(name.as_str());
+            let _ = app_handle.emit_all("port-removed", info.clone());
+            if info.in_use {
+                // 使用中のポートが消えたので接続を閉じる
+                let _ = state
+                    .command_tx
+                    .send(InternalCommand::Disconnected {
+                        port_name: name.clone(),
+                    })
+                    .await;
+            }
+        }
+        known = current;
+    }
+}
@@ -99,6 +99,55 @@ impl SequenceMsg {
             data: Some(data),
         }
     }
+    pub fn get_channel(&self) -> u8 {
+        self.channel
+    }
+    pub fn is_end(&self) -> bool {
+        self.sq_event == SequenceEventFlag::End
+    }
+    // チャンネルに依存しないイベント
+    pub fn is_global(&self) -> bool {
+        self.sq_event == SequenceEventFlag::End || self.sq_event == SequenceEventFlag::Tempo
+    }
+    // Key Event: (ノート番号, Key On か)
+    pub fn get_note(&self) -> Option<(u8, bool)> {
+        match (&self.sq_event, self.get_data()) {
+            (SequenceEventFlag::KeyEvent, Some(data)) if data.len() >= 2 => {
+                Some((data[0], data[1] != 0))
+            }
+            _ => None,
+        }
+    }
+    pub fn get_tempo(&self) -> Option<u32> {
+        match (&self.sq_event, self.get_data()) {
+            (SequenceEventFlag::Tempo, Some(data)) if data.len() >= 4 => {
+                Some(u32::from_ne_bytes([data[0], data[1], data[2], data[3]]))
+            }
+            _ => None,
+        }
+    }
+    pub fn get_instrument(&self) -> Option<String> {
+        match (&self.sq_event, self.get_data()) {
+            (SequenceEventFlag::ProgramChange, Some(data)) => {
+                Some(String::from_utf8_lossy(data).trim_end_matches('\0').to_string())
+            }
+            _ => None,
+        }
+    }
+    pub fn get_pitch_bend(&self) -> Option<i32> {
+        match (&self.sq_event, self.get_data()) {
+            (SequenceEventFlag::PitchBend, Some(data)) if data.len() >= 2 => {
+                Some(((data[0] as i32) | ((data[1] as i32) << 8)) - 8192)
+            }
+            _ => None,
+        }
+    }
+    pub fn get_expression(&self) -> Option<u8> {
+        match (&self.sq_event, self.get_data()) {
+            (SequenceEventFlag::Expression, Some(data)) if !data.is_empty() => Some(data[0]),
+            _ => None,
+        }
+    }
     fn get_data(&self) -> Option<&[u8]> {
         if let Some(data) = &self.data {
             Some(data.as_slice())
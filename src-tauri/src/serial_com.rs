@@ -1,19 +1,52 @@
+use crate::logger::RAW_TARGET;
 use crate::sequence_msg::{SequenceEventFlag, SequenceMsg};
 use serial2_tokio::SerialPort;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use ymodem_send_rs::{YmodemAsyncSend, YmodemSender};
 pub enum Message {
     Sequence(SequenceMsg),
-    Printf(String),
-    Message(String),
+    // デコード前のバイト列(文字コードは受け取る側で選ぶ)
+    Printf(Vec<u8>),
+    Status(DeviceStatus),
+}
+// ローダ/シーケンサが1バイトで送ってくる状態通知
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceStatus {
+    LoaderWait,
+    LoadSuccess,
+    LoadFailed,
+    SequencerReady,
+}
+impl DeviceStatus {
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(Self::LoaderWait),
+            0xd => Some(Self::LoadSuccess),
+            0xa => Some(Self::LoadFailed),
+            0xE => Some(Self::SequencerReady),
+            _ => None,
+        }
+    }
+}
+impl std::fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LoaderWait => write!(f, "Loader start wait."),
+            Self::LoadSuccess => write!(f, "load success"),
+            Self::LoadFailed => write!(f, "load failed"),
+            Self::SequencerReady => write!(f, "Sequencer ready"),
+        }
+    }
 }
 impl From<SequenceMsg> for Message {
     fn from(value: SequenceMsg) -> Self {
         Message::Sequence(value)
     }
 }
-impl From<String> for Message {
-    fn from(value: String) -> Self {
+impl From<Vec<u8>> for Message {
+    fn from(value: Vec<u8>) -> Self {
         Message::Printf(value)
     }
 }
@@ -23,123 +56,184 @@ pub async fn file_size(port: &mut SerialPort, buf: &[u8]) -> Result<(), String>
     let all_data: [u8; 4] = [bit4_header, f_size[0], f_size[1], f_size[2]];
 
     // シリアルポートにデータを書き込む
+    log::trace!(target: RAW_TARGET, "tx {:02x?}", all_data);
     port.write_all(&all_data)
         .await
         .map_err(|e| format!("Failed to write to serial port: {}", e))?;
-    println!("File size sent!");
     Ok(())
 }
 
-pub async fn file_data(port: &mut SerialPort, data: &[u8]) {
-    println!("Start Send MIDI FIle by Ymodem");
+pub async fn file_data(port: &mut SerialPort, data: &[u8]) -> Result<(), SendError> {
     let fname = "example.mid";
+    log::trace!(target: RAW_TARGET, "tx ymodem {} bytes", data.len());
     let sender = YmodemSender::new(fname, data);
-    sender.send(port).await.unwrap();
-    println!("Maybe File sent!");
+    sender
+        .send(port)
+        .await
+        .map_err(|e| SendError::Io(format!("YMODEM transfer failed: {e:?}")))
 }
 // Receive only one byte
 pub async fn receive_byte(port: &mut SerialPort) -> Result<u8, String> {
+    read_byte(port).await.map_err(|e| e.to_string())
+}
+
+async fn read_byte(port: &mut SerialPort) -> std::io::Result<u8> {
     let mut response = [0; 1];
-    match port.read_exact(&mut response).await {
-        Ok(_) => Ok(response[0]),
-        Err(e) => {
-            println!("Failed to read from serial port:\n  {:?}", e);
-            Err(e.to_string())
+    port.read_exact(&mut response).await?;
+    log::trace!(target: RAW_TARGET, "rx {:02x}", response[0]);
+    Ok(response[0])
+}
+
+// MIDIファイル転送の進行段階
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadStage {
+    FileSize,
+    WaitAccept,
+    Transfer,
+    WaitResult,
+    Done,
+}
+impl std::fmt::Display for UploadStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FileSize => write!(f, "Send File Size"),
+            Self::WaitAccept => write!(f, "Wait for Sequencer"),
+            Self::Transfer => write!(f, "Transfer File Data"),
+            Self::WaitResult => write!(f, "Wait for Result"),
+            Self::Done => write!(f, "Done"),
         }
     }
 }
 
-pub async fn send_midi_file(port: &mut SerialPort, buf: &[u8]) -> Result<(), String> {
-    file_size(port, buf).await.unwrap();
+// MIDIファイル転送の失敗要因
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendError {
+    Io(String),
+    Refused,
+    Failed,
+    UnexpectedResponse(u8),
+    // 応答を待っていた段階
+    Timeout(UploadStage),
+}
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(msg) => write!(f, "{msg}"),
+            Self::Refused => write!(f, "Communication partner is not accepting."),
+            Self::Failed => write!(f, "failed to send  midi file"),
+            Self::UnexpectedResponse(v) => write!(f, "unexpected response: {:#01X}", v),
+            Self::Timeout(UploadStage::WaitAccept) => {
+                write!(f, "Timed out waiting for the sequencer")
+            }
+            Self::Timeout(_) => write!(f, "Timed out waiting for the result"),
+        }
+    }
+}
+impl From<String> for SendError {
+    fn from(value: String) -> Self {
+        SendError::Io(value)
+    }
+}
+
+pub async fn send_midi_file(port: &mut SerialPort, buf: &[u8]) -> Result<(), SendError> {
+    send_midi_file_with_progress(port, buf, None, |_| {}).await
+}
+
+// 応答を1バイト待つ(YMODEM転送そのものには時間制限をかけない)
+async fn receive_response(
+    port: &mut SerialPort,
+    timeout: Option<Duration>,
+    stage: UploadStage,
+) -> Result<u8, SendError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, receive_byte(port))
+            .await
+            .map_err(|_| SendError::Timeout(stage))?
+            .map_err(SendError::from),
+        None => Ok(receive_byte(port).await?),
+    }
+}
+
+// 転送の各段階でon_stageを呼ぶ
+// response_timeout: シーケンサの応答(受信可能/結果)を待つ時間の上限
+pub async fn send_midi_file_with_progress(
+    port: &mut SerialPort,
+    buf: &[u8],
+    response_timeout: Option<Duration>,
+    mut on_stage: impl FnMut(UploadStage),
+) -> Result<(), SendError> {
+    on_stage(UploadStage::FileSize);
+    file_size(port, buf).await?;
     // Ymodemによるファイル転送(受信可能の場合)
-    let msg_flag = receive_byte(port).await.unwrap() & 0xf;
+    on_stage(UploadStage::WaitAccept);
+    let msg_flag = receive_response(port, response_timeout, UploadStage::WaitAccept).await? & 0xf;
     if msg_flag == 0xe {
-        file_data(port, buf).await;
+        on_stage(UploadStage::Transfer);
+        file_data(port, buf).await?;
     } else {
-        println!("Communication partner is not accepting.");
-        return Err(String::from("Communication partner is not accepting."));
+        return Err(SendError::Refused);
     }
-    let msg_flag = receive_byte(port).await.unwrap() & 0xf;
+    on_stage(UploadStage::WaitResult);
+    let msg_flag = receive_response(port, response_timeout, UploadStage::WaitResult).await? & 0xf;
     if msg_flag == 0xc {
-        println!("failed to send  midi file");
-        Err(String::from("failed to send  midi file"))
+        Err(SendError::Failed)
     } else if msg_flag == 0xd {
-        println!("success to send  midi file");
+        on_stage(UploadStage::Done);
         Ok(())
     } else {
-        println!("received: {:#01X}", msg_flag);
-        unreachable!();
+        Err(SendError::UnexpectedResponse(msg_flag))
     }
 }
 
+// フレームの途中でポートが失われた場合は Err を返す
 pub async fn receive_sequence_msg(
     first_byte: u8,
     port: &mut serial2_tokio::SerialPort,
-) -> Option<Message> {
-    if first_byte == 0x0 {
-      println!("Loader start wait.");
-      return Some(Message::Message("Loader start wait.".to_string()));
-    } else if first_byte == 0xd {
-      println!("load success");
-      return Some(Message::Message("load success".to_string()));
-    } else if first_byte == 0xa {
-      println!("load failed");
-      return Some(Message::Message("load failed".to_string()));
-    } else if first_byte == 0xE {
-      println!("Sequencer ready");
-      return Some(Message::Message("Sequencer ready".to_string()));
+) -> std::io::Result<Option<Message>> {
+    if let Some(status) = DeviceStatus::from_byte(first_byte) {
+      return Ok(Some(Message::Status(status)));
     }
     let msg_flag = first_byte & 0xf;
     let len = (first_byte >> 4) as usize;
     if len == 0 && msg_flag == 1 {
         // End Event(継続するデータなし)
-        println!("NOP OR END");
-        return Some(Message::from(SequenceMsg::new(
+        return Ok(Some(Message::from(SequenceMsg::new(
             0,
             SequenceEventFlag::End,
             None,
-        )));
+        ))));
     } else if msg_flag != 0x1 && msg_flag != 0x07 {
       // 読み捨てコード
       // let mut buf = vec![0u8; len];
       // port.read_exact(&mut buf).await.unwrap();
-      println!("receive: {:#02x}", msg_flag);
-      return None
+      log::debug!("unknown message flag: {:#02x}", msg_flag);
+      return Ok(None)
     }
     let len = if (msg_flag & 0xf) == 0x7 {
         // Printf protocol length
-        println!("!printf protocol!");
-        let low_byte = crate::serial_com::receive_byte(port).await.unwrap();
-        let high_byte = crate::serial_com::receive_byte(port).await.unwrap();
+        let low_byte = read_byte(port).await?;
+        let high_byte = read_byte(port).await?;
         ((high_byte as usize) << 8) | (low_byte as usize)
     } else {
       // Sequence msg Protocol
-        let high_byte = crate::serial_com::receive_byte(port).await.unwrap();
+        let high_byte = read_byte(port).await?;
         len | ((high_byte as usize) << 4)
     };
-    println!("len: {len}");
     let mut buf = vec![0; len];
-    port.read_exact(&mut buf).await.unwrap();
+    port.read_exact(&mut buf).await?;
+    log::trace!(target: RAW_TARGET, "rx {:02x?}", buf);
     if msg_flag == 0x7 {
         // Printfのメッセージ
-        let str = std::str::from_utf8(&buf).unwrap().to_string();
-        return Some(Message::from(str));
+        return Ok(Some(Message::from(buf)));
     } else if msg_flag != 1 {
-        println!("receive: {:#02x}", msg_flag);
-        return None;
+        log::debug!("unknown message flag: {:#02x}", msg_flag);
+        return Ok(None);
     }
 
-    Some(Message::from(SequenceMsg::from(buf.as_slice())))
+    Ok(Some(Message::from(SequenceMsg::from(buf.as_slice()))))
 }
 
 pub fn clear_buffer(port: &mut SerialPort) {
     port.discard_input_buffer().unwrap();
     port.discard_output_buffer().unwrap();
 }
-async fn send_text(port: &mut SerialPort, text: &str) {
-    port.write_all(text.as_bytes()).await.unwrap()
-}
-pub async fn send_raw_text_file(port: &mut SerialPort, fname: impl AsRef<std::path::Path>) {
-    let file = std::fs::read_to_string(fname).unwrap();
-    send_text(port, &file).await;
-}
@@ -0,0 +1,133 @@
+// シリアルポートの通信設定(GUI/CLI共通)
+use crate::device_printf::PrintfEncoding;
+use serial2_tokio::SerialPort;
+
+#[derive(serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlowControl {
+    None,
+    XonXoff,
+    RtsCts,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub char_size: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    // 未指定ならドライバの既定値のまま
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    // デバイスのprintf出力の文字コード
+    pub printf_encoding: PrintfEncoding,
+}
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            char_size: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            dtr: None,
+            rts: None,
+            printf_encoding: PrintfEncoding::default(),
+        }
+    }
+}
+impl std::fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        write!(
+            f,
+            "{} {}{}{} flow:{:?}",
+            self.baud_rate, self.char_size, parity, self.stop_bits, self.flow_control
+        )
+    }
+}
+
+impl SerialSettings {
+    // serial2で設定可能な値か確認する
+    pub fn validate(&self) -> Result<(), String> {
+        if self.baud_rate == 0 {
+            return Err("baud rate must be greater than 0".to_string());
+        }
+        serial2::CharSize::try_from(self.char_size)
+            .map_err(|_| format!("unsupported data bits: {}", self.char_size))?;
+        serial2::StopBits::try_from(self.stop_bits)
+            .map_err(|_| format!("unsupported stop bits: {}", self.stop_bits))?;
+        if self.flow_control == FlowControl::RtsCts && self.rts.is_some() {
+            return Err("RTS cannot be set manually with RTS/CTS flow control".to_string());
+        }
+        Ok(())
+    }
+
+    // 設定を適用してポートを開く(ポートがない場合は ErrorKind::NotFound)
+    pub fn open(&self, port_name: &str) -> std::io::Result<SerialPort> {
+        self.validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let settings = self.clone();
+        let port = SerialPort::open(port_name, move |mut config: serial2::Settings| {
+            config.set_raw();
+            config.set_baud_rate(settings.baud_rate)?;
+            config.set_char_size(serial2::CharSize::try_from(settings.char_size).unwrap());
+            config.set_stop_bits(serial2::StopBits::try_from(settings.stop_bits).unwrap());
+            config.set_parity(match settings.parity {
+                Parity::None => serial2::Parity::None,
+                Parity::Odd => serial2::Parity::Odd,
+                Parity::Even => serial2::Parity::Even,
+            });
+            config.set_flow_control(match settings.flow_control {
+                FlowControl::None => serial2::FlowControl::None,
+                FlowControl::XonXoff => serial2::FlowControl::XonXoff,
+                FlowControl::RtsCts => serial2::FlowControl::RtsCts,
+            });
+            Ok(config)
+        })
+        .map_err(|e| with_context(e, format!("failed to open {port_name}")))?;
+        if let Some(dtr) = self.dtr {
+            port.set_dtr(dtr).map_err(|e| with_context(e, "failed to set DTR"))?;
+        }
+        if let Some(rts) = self.rts {
+            port.set_rts(rts).map_err(|e| with_context(e, "failed to set RTS"))?;
+        }
+        Ok(port)
+    }
+
+    // 実際にポートへ適用された設定を読み出す
+    pub fn applied(&self, port: &SerialPort) -> Self {
+        let mut applied = self.clone();
+        if let Ok(config) = port.get_configuration() {
+            if let Ok(baud_rate) = config.get_baud_rate() {
+                applied.baud_rate = baud_rate;
+            }
+            if let Ok(char_size) = config.get_char_size() {
+                applied.char_size = char_size.as_u8();
+            }
+            if let Ok(stop_bits) = config.get_stop_bits() {
+                applied.stop_bits = stop_bits.as_u8();
+            }
+        }
+        applied
+    }
+}
+
+// ErrorKind を保ったままメッセージを付ける
+fn with_context(e: std::io::Error, context: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{context}: {e}"))
+}
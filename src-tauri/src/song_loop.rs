@@ -0,0 +1,153 @@
+// 演奏中の曲のループ再生(End を受け取ったら同じデータを送り直す)
+// ファームウェアに再演奏の要求がないため、毎回アップロードし直す
+use crate::commands::{self, InternalCommand};
+use crate::AppState;
+use std::sync::Arc;
+use tauri::Manager;
+
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct SongLoop {
+    active: bool,
+    // 演奏する回数(None なら止めるまで繰り返す)
+    count: Option<u32>,
+    // 演奏した回数(送信中/演奏中の回を含む)
+    played: u32,
+    // 切り出した範囲を繰り返す場合のデータ(None なら読み込み済みの曲)
+    #[serde(skip)]
+    excerpt: Option<Arc<[u8]>>,
+}
+impl SongLoop {
+    // 次も送り直すか
+    fn has_next(&self) -> bool {
+        self.active && self.count.is_none_or(|count| self.played < count)
+    }
+}
+
+#[derive(serde::Serialize, Clone)]
+struct LoopEvent {
+    #[serde(flatten)]
+    state: SongLoop,
+    message: Option<String>,
+}
+
+fn notify<R: tauri::Runtime>(manager: &impl Manager<R>, message: Option<String>) {
+    let state = manager
+        .state::<AppState>()
+        .song_loop
+        .lock()
+        .unwrap()
+        .clone();
+    match &message {
+        Some(message) => log::warn!("loop: {message}"),
+        None => log::info!(
+            "loop: {}/{:?} (active={})",
+            state.played,
+            state.count,
+            state.active
+        ),
+    }
+    let _ = manager.emit_all("loop-changed", LoopEvent { state, message });
+}
+
+pub fn get<R: tauri::Runtime>(manager: &impl Manager<R>) -> SongLoop {
+    manager
+        .state::<AppState>()
+        .song_loop
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+// count 回演奏するまで(None なら止めるまで)繰り返す
+// playing: 既に演奏中ならその回を1回目として数える
+// excerpt: 切り出した範囲を繰り返す場合のデータ
+pub fn start<R: tauri::Runtime>(
+    manager: &impl Manager<R>,
+    count: Option<u32>,
+    playing: bool,
+    excerpt: Option<Vec<u8>>,
+) -> Result<(), String> {
+    if count == Some(0) {
+        return Err("loop count must be at least 1".to_string());
+    }
+    *manager.state::<AppState>().song_loop.lock().unwrap() = SongLoop {
+        active: true,
+        count,
+        played: u32::from(playing),
+        excerpt: excerpt.map(Arc::from),
+    };
+    notify(manager, None);
+    Ok(())
+}
+
+// ループをやめる(演奏中の回はそのまま最後まで演奏する)
+pub fn stop<R: tauri::Runtime>(manager: &impl Manager<R>) {
+    let state = manager.state::<AppState>();
+    state.song_loop.lock().unwrap().active = false;
+    if let Some(task) = state.loop_task.lock().unwrap().take() {
+        task.abort();
+    }
+    notify(manager, None);
+}
+
+// 送信が始まった回を数える
+pub fn on_send<R: tauri::Runtime>(manager: &impl Manager<R>) {
+    let counted = {
+        let mut song_loop = manager.state::<AppState>().song_loop.lock().unwrap();
+        if song_loop.active {
+            song_loop.played += 1;
+        }
+        song_loop.active
+    };
+    if counted {
+        notify(manager, None);
+    }
+}
+
+// シーケンサから End を受け取ったときに呼ぶ(送り直す場合は true)
+pub fn on_end<R: tauri::Runtime>(manager: &impl Manager<R>) -> bool {
+    let state = manager.state::<AppState>();
+    let (finished, has_next) = {
+        let mut song_loop = state.song_loop.lock().unwrap();
+        let finished = song_loop.active && !song_loop.has_next();
+        if finished {
+            song_loop.active = false;
+        }
+        (finished, song_loop.has_next())
+    };
+    if finished {
+        notify(manager, None);
+    }
+    if !has_next {
+        return false;
+    }
+    let handle = tauri::async_runtime::spawn(replay(manager.app_handle()));
+    if let Some(old) = state.loop_task.lock().unwrap().replace(handle) {
+        old.abort();
+    }
+    true
+}
+
+async fn replay<R: tauri::Runtime>(app_handle: tauri::AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    let excerpt = state.song_loop.lock().unwrap().excerpt.clone();
+    let data = match excerpt {
+        Some(excerpt) => Some(excerpt.to_vec()),
+        None => state
+            .file_data
+            .lock()
+            .await
+            .as_ref()
+            .map(|file| file.data.clone()),
+    };
+    let result = match data {
+        Some(data) => {
+            commands::request(&state, |reply| InternalCommand::Send { data, reply }).await
+        }
+        None => Err("MIDI file is not opened".to_string()),
+    };
+    if let Err(e) = result {
+        state.song_loop.lock().unwrap().active = false;
+        notify(&app_handle, Some(e));
+    }
+}
@@ -0,0 +1,374 @@
+// Motorola S-record の解析と検証(書き込み前のチェック用)
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrecError {
+    // 1始まりの行番号(0はファイル全体の問題)
+    pub line: usize,
+    pub message: String,
+}
+impl std::fmt::Display for SrecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+fn error(line: usize, message: impl Into<String>) -> SrecError {
+    SrecError {
+        line,
+        message: message.into(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub line: usize,
+    // S0〜S9 の数字
+    pub kind: u8,
+    pub address: u32,
+    pub data: Vec<u8>,
+    // 送信用の元の行(改行なし)
+    pub text: String,
+}
+impl Record {
+    pub fn is_data(&self) -> bool {
+        matches!(self.kind, 1..=3)
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct AddressRange {
+    pub start: u32,
+    // 終端は含まない
+    pub end: u32,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct SrecSummary {
+    pub header: Option<String>,
+    // 16/24/32
+    pub address_width: u8,
+    pub ranges: Vec<AddressRange>,
+    pub data_bytes: usize,
+    pub record_count: usize,
+    pub entry_point: Option<u32>,
+}
+impl std::fmt::Display for SrecSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(header) = &self.header {
+            writeln!(f, "header: {header}")?;
+        }
+        writeln!(
+            f,
+            "{} records, {} bytes ({}-bit address)",
+            self.record_count, self.data_bytes, self.address_width
+        )?;
+        for range in &self.ranges {
+            writeln!(
+                f,
+                "  {:#010X}-{:#010X} ({} bytes)",
+                range.start,
+                range.end - 1,
+                range.end - range.start
+            )?;
+        }
+        match self.entry_point {
+            Some(entry) => write!(f, "entry point: {entry:#010X}"),
+            None => write!(f, "entry point: none"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SrecFile {
+    pub records: Vec<Record>,
+}
+
+impl SrecFile {
+    pub fn parse(text: &str) -> Result<Self, SrecError> {
+        let mut records = Vec::new();
+        for (i, raw) in text.lines().enumerate() {
+            let raw = raw.trim();
+            if raw.is_empty() {
+                continue;
+            }
+            records.push(parse_record(i + 1, raw)?);
+        }
+        let file = Self { records };
+        file.validate()?;
+        Ok(file)
+    }
+
+    // 送信/保存用のテキスト(1行1レコード)
+    pub fn to_text(&self) -> String {
+        self.records.iter().map(|r| format!("{}\n", r.text)).collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        std::fs::write(path, self.to_text()).map_err(|e| e.to_string())
+    }
+
+    pub fn data_records(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter().filter(|r| r.is_data())
+    }
+
+    // アドレス順に並べ、連続するデータをまとめた領域
+    pub fn segments(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut sorted = self
+            .data_records()
+            .filter(|r| !r.data.is_empty())
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|r| r.address);
+        let mut segments: Vec<(u32, Vec<u8>)> = Vec::new();
+        for record in sorted {
+            match segments.last_mut() {
+                Some((start, data)) if *start as u64 + data.len() as u64 == record.address as u64 => {
+                    data.extend_from_slice(&record.data)
+                }
+                _ => segments.push((record.address, record.data.clone())),
+            }
+        }
+        segments
+    }
+
+    // 全体の整合性(アドレス幅の混在、重なり、レコード数、開始アドレス)
+    fn validate(&self) -> Result<(), SrecError> {
+        let mut data_kind = None;
+        for record in self.data_records() {
+            match data_kind {
+                None => data_kind = Some(record.kind),
+                Some(kind) if kind != record.kind => {
+                    return Err(error(
+                        record.line,
+                        format!("S{} record mixed with S{kind} records", record.kind),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if data_kind.is_none() {
+            return Err(error(0, "no data records"));
+        }
+        // 重なりの検出(開始アドレス順に並べて隣と比べる)
+        let mut sorted = self
+            .data_records()
+            .filter(|r| !r.data.is_empty())
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|r| r.address);
+        for pair in sorted.windows(2) {
+            let end = pair[0].address as u64 + pair[0].data.len() as u64;
+            if end > pair[1].address as u64 {
+                return Err(error(
+                    pair[1].line,
+                    format!(
+                        "address {:#X} overlaps with line {}",
+                        pair[1].address, pair[0].line
+                    ),
+                ));
+            }
+        }
+        let data_count = self.data_records().count();
+        for record in self.records.iter().filter(|r| matches!(r.kind, 5 | 6)) {
+            if record.address as usize != data_count {
+                return Err(error(
+                    record.line,
+                    format!(
+                        "record count {} does not match {data_count} data records",
+                        record.address
+                    ),
+                ));
+            }
+        }
+        let terminators = self
+            .records
+            .iter()
+            .filter(|r| matches!(r.kind, 7..=9))
+            .collect::<Vec<_>>();
+        if terminators.len() > 1 {
+            return Err(error(terminators[1].line, "multiple start address records"));
+        }
+        // S1/S9, S2/S8, S3/S7 の組み合わせ
+        if let (Some(kind), Some(term)) = (data_kind, terminators.first()) {
+            if kind + term.kind != 10 {
+                return Err(error(
+                    term.line,
+                    format!("S{} record does not match S{kind} data records", term.kind),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn summary(&self) -> SrecSummary {
+        let header = self
+            .records
+            .iter()
+            .find(|r| r.kind == 0)
+            .map(|r| String::from_utf8_lossy(&r.data).trim_end_matches('\0').to_string());
+        let address_width = match self.data_records().next().map(|r| r.kind) {
+            Some(2) => 24,
+            Some(3) => 32,
+            _ => 16,
+        };
+        let mut sorted = self.data_records().collect::<Vec<_>>();
+        sorted.sort_by_key(|r| r.address);
+        // 連続する領域をまとめる
+        let mut ranges: Vec<AddressRange> = Vec::new();
+        for record in sorted.iter().filter(|r| !r.data.is_empty()) {
+            let end = record.address.saturating_add(record.data.len() as u32);
+            match ranges.last_mut() {
+                Some(last) if last.end == record.address => last.end = end,
+                _ => ranges.push(AddressRange {
+                    start: record.address,
+                    end,
+                }),
+            }
+        }
+        SrecSummary {
+            header,
+            address_width,
+            ranges,
+            data_bytes: sorted.iter().map(|r| r.data.len()).sum(),
+            record_count: self.records.len(),
+            entry_point: self
+                .records
+                .iter()
+                .find(|r| matches!(r.kind, 7..=9))
+                .map(|r| r.address),
+        }
+    }
+}
+
+// アドレス部のバイト数
+fn address_len(kind: u8) -> Option<usize> {
+    match kind {
+        0 | 1 | 5 | 9 => Some(2),
+        2 | 6 | 8 => Some(3),
+        3 | 7 => Some(4),
+        _ => None,
+    }
+}
+
+fn parse_record(line: usize, raw: &str) -> Result<Record, SrecError> {
+    let bytes = raw.as_bytes();
+    if bytes.len() < 4 || bytes[0] != b'S' || !raw.is_ascii() {
+        return Err(error(line, "not an S-record"));
+    }
+    let kind = (bytes[1] as char)
+        .to_digit(10)
+        .map(|d| d as u8)
+        .ok_or_else(|| error(line, format!("unknown record type S{}", bytes[1] as char)))?;
+    let addr_len =
+        address_len(kind).ok_or_else(|| error(line, format!("unknown record type S{kind}")))?;
+    let body = &raw[2..];
+    if body.len() % 2 != 0 {
+        return Err(error(line, "odd number of hex digits"));
+    }
+    let values = (0..body.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&body[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error(line, "invalid hex digit"))?;
+    let count = values[0] as usize;
+    if values.len() - 1 != count {
+        return Err(error(
+            line,
+            format!("byte count {count} does not match {} bytes", values.len() - 1),
+        ));
+    }
+    if count < addr_len + 1 {
+        return Err(error(line, "record too short"));
+    }
+    // カウント〜データの合計の1の補数
+    let sum = values[..values.len() - 1]
+        .iter()
+        .fold(0u8, |acc, &v| acc.wrapping_add(v));
+    let checksum = values[values.len() - 1];
+    if !sum != checksum {
+        return Err(error(
+            line,
+            format!("checksum mismatch (expected {:02X}, found {checksum:02X})", !sum),
+        ));
+    }
+    let address = values[1..1 + addr_len]
+        .iter()
+        .fold(0u32, |acc, &v| (acc << 8) | v as u32);
+    let data = values[1 + addr_len..values.len() - 1].to_vec();
+    if matches!(kind, 5..=9) && !data.is_empty() {
+        return Err(error(line, format!("S{kind} record must not have data")));
+    }
+    let end = address as u64 + data.len() as u64;
+    if end > 1u64 << (addr_len * 8) {
+        return Err(error(line, "data exceeds the address range"));
+    }
+    Ok(Record {
+        line,
+        kind,
+        address,
+        data,
+        text: raw.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "S00600004844521B";
+
+    #[test]
+    fn parse_s1_file() {
+        let text = format!("{HEADER}\nS107000001020304EE\nS9030000FC\n");
+        let file = SrecFile::parse(&text).unwrap();
+        let summary = file.summary();
+        assert_eq!(summary.header.as_deref(), Some("HDR"));
+        assert_eq!(summary.address_width, 16);
+        assert_eq!(summary.data_bytes, 4);
+        assert_eq!(summary.record_count, 3);
+        assert_eq!(summary.entry_point, Some(0));
+        assert_eq!(file.segments(), vec![(0, vec![1, 2, 3, 4])]);
+        assert_eq!(file.to_text(), text);
+    }
+
+    #[test]
+    fn parse_s2_and_s3_files() {
+        let file = SrecFile::parse("S20801000001020304EC\nS804000000FB\n").unwrap();
+        assert_eq!(file.summary().address_width, 24);
+        assert_eq!(file.segments(), vec![(0x1_0000, vec![1, 2, 3, 4])]);
+        let file = SrecFile::parse("S3090001000001020304EB\nS70500000000FA\n").unwrap();
+        assert_eq!(file.summary().address_width, 32);
+        assert_eq!(file.segments(), vec![(0x1_0000, vec![1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let text = format!("{HEADER}\nS107000001020304EF\nS9030000FC\n");
+        let e = SrecFile::parse(&text).unwrap_err();
+        assert_eq!(e.line, 2);
+        assert!(e.message.contains("checksum"));
+    }
+
+    #[test]
+    fn truncated_line() {
+        let text = format!("{HEADER}\nS10700000102\nS9030000FC\n");
+        let e = SrecFile::parse(&text).unwrap_err();
+        assert_eq!(e.line, 2);
+        assert!(e.message.contains("byte count"));
+        assert_eq!(SrecFile::parse("S1").unwrap_err().message, "not an S-record");
+        assert!(SrecFile::parse("S1070000010").is_err());
+    }
+
+    #[test]
+    fn mismatched_record_types() {
+        // S4 は存在しない
+        assert!(SrecFile::parse("S4030000FC\n").is_err());
+        // S1 データに S8 の開始アドレス
+        let e = SrecFile::parse("S107000001020304EE\nS804000000FB\n").unwrap_err();
+        assert_eq!(e.line, 2);
+        // 幅の混在
+        assert!(SrecFile::parse("S107000001020304EE\nS20801000001020304EC\n").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+// 接続状態の管理(状態遷移のたびにフロントへ status-changed を通知する)
+use crate::AppState;
+use tauri::Manager;
+use tokio::sync::watch;
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionState {
+    Disconnected,
+    Opening,
+    ConnectedIdle,
+    LoaderWaiting,
+    Uploading,
+    Playing,
+    Flashing,
+    Error,
+}
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disconnected => write!(f, "Disconnected"),
+            Self::Opening => write!(f, "Opening"),
+            Self::ConnectedIdle => write!(f, "Connected"),
+            Self::LoaderWaiting => write!(f, "Loader Waiting"),
+            Self::Uploading => write!(f, "Uploading"),
+            Self::Playing => write!(f, "Playing"),
+            Self::Flashing => write!(f, "Flashing"),
+            Self::Error => write!(f, "Error"),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    pub port: Option<String>,
+    // Error のときの原因
+    pub message: Option<String>,
+}
+impl Default for ConnectionStatus {
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Disconnected,
+            port: None,
+            message: None,
+        }
+    }
+}
+impl ConnectionStatus {
+    pub fn new(state: ConnectionState, port: Option<String>) -> Self {
+        Self {
+            state,
+            port,
+            message: None,
+        }
+    }
+    // ポートが開いている状態か
+    pub fn is_connected(&self) -> bool {
+        !matches!(
+            self.state,
+            ConnectionState::Disconnected | ConnectionState::Opening
+        ) && self.port.is_some()
+    }
+}
+
+pub fn new_status_channel() -> watch::Sender<ConnectionStatus> {
+    watch::Sender::new(ConnectionStatus::default())
+}
+
+pub fn get_status<R: tauri::Runtime>(manager: &impl Manager<R>) -> ConnectionStatus {
+    manager.state::<AppState>().status.borrow().clone()
+}
+
+// 状態を更新してフロントへ通知する(ポートは現在のものを引き継ぐ)
+pub fn set_state<R: tauri::Runtime>(manager: &impl Manager<R>, state: ConnectionState) {
+    let port = get_status(manager).port;
+    set_status(manager, ConnectionStatus::new(state, port));
+}
+
+pub fn set_error<R: tauri::Runtime>(manager: &impl Manager<R>, message: impl Into<String>) {
+    let port = get_status(manager).port;
+    set_status(
+        manager,
+        ConnectionStatus {
+            state: ConnectionState::Error,
+            port,
+            message: Some(message.into()),
+        },
+    );
+}
+
+pub fn set_status<R: tauri::Runtime>(manager: &impl Manager<R>, status: ConnectionStatus) {
+    manager.state::<AppState>().status.send_replace(status.clone());
+    let _ = manager.emit_all("status-changed", status);
+}
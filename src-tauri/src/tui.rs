@@ -0,0 +1,302 @@
+// CLI用のターミナルダッシュボード(GUIのPerformance Monitor相当)
+use crate::device_printf::{PrintfBuffer, PrintfFilter, PrintfLine};
+use crate::serial_com::{self, Message, UploadStage};
+use crate::sequence_msg::SequenceMsg;
+use crossterm::{
+    cursor::Show,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use serial2_tokio::SerialPort;
+use std::collections::VecDeque;
+use tokio::sync::mpsc;
+
+const MAX_CHANNEL: usize = 6;
+const MAX_LOG_LINES: usize = 500;
+const NOTE_NAME: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+enum DashboardEvent {
+    Status(String),
+    Upload(String),
+    Message(Message),
+    Quit,
+}
+
+struct ChannelState {
+    instrument: String,
+    note: String,
+    pitch: i32,
+    expression: u8,
+}
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            instrument: "unknown".to_string(),
+            note: "OFF".to_string(),
+            pitch: 0,
+            expression: 127,
+        }
+    }
+}
+
+struct Dashboard {
+    port_name: String,
+    status: String,
+    upload: String,
+    tempo: Option<u32>,
+    channels: Vec<ChannelState>,
+    logs: VecDeque<String>,
+}
+impl Dashboard {
+    fn new(port_name: String) -> Self {
+        Self {
+            port_name,
+            status: "Connected".to_string(),
+            upload: "-".to_string(),
+            tempo: None,
+            channels: (0..MAX_CHANNEL).map(|_| ChannelState::default()).collect(),
+            logs: VecDeque::with_capacity(MAX_LOG_LINES),
+        }
+    }
+    fn log(&mut self, line: String) {
+        if self.logs.len() == MAX_LOG_LINES {
+            self.logs.pop_front();
+        }
+        self.logs.push_back(line);
+    }
+    fn printf(&mut self, line: &PrintfLine, filter: &PrintfFilter) {
+        if filter.matches(line) {
+            self.log(format!("printf> {}", line.text));
+        }
+    }
+    fn update(&mut self, msg: &SequenceMsg) {
+        if let Some(tempo) = msg.get_tempo() {
+            self.tempo = Some(tempo);
+            return;
+        }
+        if msg.is_end() {
+            self.log("End".to_string());
+            return;
+        }
+        let Some(ch) = self.channels.get_mut(msg.get_channel() as usize) else {
+            return;
+        };
+        if let Some((note, key_on)) = msg.get_note() {
+            ch.note = format!(
+                "{} {}{}({note})",
+                if key_on { "ON" } else { "OFF" },
+                NOTE_NAME[(note % 12) as usize],
+                (note / 12) as i32 - 1
+            );
+        } else if let Some(instrument) = msg.get_instrument() {
+            ch.instrument = instrument;
+        } else if let Some(pitch) = msg.get_pitch_bend() {
+            ch.pitch = pitch;
+        } else if let Some(expression) = msg.get_expression() {
+            ch.expression = expression;
+        }
+    }
+}
+
+// raw mode と代替画面を元に戻す(エラーやパニックで抜けた場合も含む)
+struct TerminalGuard;
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen, Show);
+    }
+}
+
+// ダッシュボードを表示し、q/Esc/Ctrl-C で終了する
+// printf はモニタ表示と同じ PrintfBuffer で行単位にまとめ、filter に合う行だけ表示する
+pub async fn run(
+    port: SerialPort,
+    port_name: String,
+    input: Option<Vec<u8>>,
+    mut printf: PrintfBuffer,
+    filter: PrintfFilter,
+) -> std::io::Result<()> {
+    enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    spawn_key_reader(tx.clone());
+    let serial = tokio::spawn(serial_task(port, input, tx));
+    let mut dashboard = Dashboard::new(port_name);
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| draw(frame, &dashboard)) {
+            break Err(e);
+        }
+        let Some(event) = rx.recv().await else {
+            break Ok(());
+        };
+        match event {
+            DashboardEvent::Status(status) => {
+                // 切断時は改行待ちの断片も表示する
+                if let Some(line) = printf.flush() {
+                    dashboard.printf(&line, &filter);
+                }
+                dashboard.log(status.clone());
+                dashboard.status = status;
+            }
+            DashboardEvent::Upload(stage) => dashboard.upload = stage,
+            DashboardEvent::Message(Message::Sequence(msg)) => dashboard.update(&msg),
+            DashboardEvent::Message(Message::Printf(bytes)) => {
+                for line in printf.push(&bytes) {
+                    dashboard.printf(&line, &filter);
+                }
+            }
+            DashboardEvent::Message(Message::Status(status)) => {
+                dashboard.log(format!("[{status}]"))
+            }
+            DashboardEvent::Quit => break Ok(()),
+        }
+    };
+    serial.abort();
+    result
+}
+
+// crosstermのキー入力はブロッキングなので別スレッドで待つ
+fn spawn_key_reader(tx: mpsc::UnboundedSender<DashboardEvent>) {
+    std::thread::spawn(move || loop {
+        if let Ok(Event::Key(key)) = event::read() {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+            if quit {
+                let _ = tx.send(DashboardEvent::Quit);
+                break;
+            }
+        }
+    });
+}
+
+async fn serial_task(
+    mut port: SerialPort,
+    input: Option<Vec<u8>>,
+    tx: mpsc::UnboundedSender<DashboardEvent>,
+) {
+    if let Some(buf) = input {
+        let size = buf.len();
+        let result = serial_com::send_midi_file_with_progress(&mut port, &buf, None, |stage| {
+            let text = if stage == UploadStage::Transfer {
+                format!("{stage} ({size} bytes)")
+            } else {
+                stage.to_string()
+            };
+            let _ = tx.send(DashboardEvent::Upload(text));
+        })
+        .await;
+        if let Err(msg) = result {
+            let _ = tx.send(DashboardEvent::Upload(format!("Failed: {msg}")));
+        }
+    }
+    loop {
+        match serial_com::receive_byte(&mut port).await {
+            Ok(v) => match serial_com::receive_sequence_msg(v, &mut port).await {
+                Ok(Some(msg)) => {
+                    if tx.send(DashboardEvent::Message(msg)).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = tx.send(DashboardEvent::Status(format!("Disconnected: {e}")));
+                    break;
+                }
+            },
+            Err(e) => {
+                let _ = tx.send(DashboardEvent::Status(format!("Disconnected: {e}")));
+                break;
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+    let area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(MAX_CHANNEL as u16 + 3),
+            Constraint::Min(5),
+        ])
+        .split(frame.size());
+    draw_header(frame, dashboard, area[0]);
+    draw_channels(frame, dashboard, area[1]);
+    draw_logs(frame, dashboard, area[2]);
+}
+
+fn draw_header(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let tempo = dashboard
+        .tempo
+        .map(|bpm| format!("{bpm} BPM"))
+        .unwrap_or_else(|| "-".to_string());
+    let line = Line::from(format!(
+        "Port: {}  Status: {}  Upload: {}  Tempo: {}",
+        dashboard.port_name, dashboard.status, dashboard.upload, tempo
+    ));
+    let header = Paragraph::new(line).block(
+        Block::default()
+            .title("MIQS Performance Monitor (q: quit)")
+            .borders(Borders::ALL),
+    );
+    frame.render_widget(header, area);
+}
+
+fn draw_channels(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let header = Row::new(vec!["channel", "instrument", "note", "pitch", "Expr"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = dashboard.channels.iter().enumerate().map(|(i, ch)| {
+        Row::new(vec![
+            format!("Ch{}", i + 1),
+            ch.instrument.clone(),
+            ch.note.clone(),
+            ch.pitch.to_string(),
+            ch.expression.to_string(),
+        ])
+    });
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Length(12),
+        Constraint::Length(14),
+        Constraint::Length(8),
+        Constraint::Length(6),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().title("Channels").borders(Borders::ALL));
+    frame.render_widget(table, area);
+}
+
+fn draw_logs(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    // 末尾から表示可能な行数だけ表示する
+    let height = area.height.saturating_sub(2) as usize;
+    let skip = dashboard.logs.len().saturating_sub(height);
+    let lines = dashboard
+        .logs
+        .iter()
+        .skip(skip)
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<_>>();
+    let logs = Paragraph::new(lines)
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().title("Log").borders(Borders::ALL));
+    frame.render_widget(logs, area);
+}
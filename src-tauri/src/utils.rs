@@ -4,15 +4,123 @@ pub fn check_midi_format(contents: &[u8]) -> bool {
     contents.starts_with(b"MThd")
 }
 
+// 読み込み済みのMIDIファイル(送る・監視する・切り出す元はすべてこれ)
+#[derive(Clone)]
+pub struct MidiFile {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
 pub fn get_serial_port_list() -> Option<Vec<String>> {
     if let Ok(ports_info) = SerialPort::available_ports() {
         Some(
             ports_info
                 .into_iter()
-                .map(|info| info.to_str().unwrap().to_string())
+                .map(|info| info.to_string_lossy().to_string())
                 .collect::<Vec<String>>(),
         )
     } else {
         None
     }
 }
+
+// シリアルポートの識別情報
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct PortInfo {
+    pub name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    // このアプリが開いているか
+    pub in_use: bool,
+    // 表示用の名前 e.g. "MIQS board (FTDI, SN 1234)"
+    pub label: String,
+}
+impl PortInfo {
+    fn new(name: String, usb: Option<serialport::UsbPortInfo>) -> Self {
+        let (vid, pid, manufacturer, product, serial_number) = match usb {
+            Some(usb) => (
+                Some(usb.vid),
+                Some(usb.pid),
+                usb.manufacturer,
+                usb.product,
+                usb.serial_number,
+            ),
+            None => (None, None, None, None, None),
+        };
+        let detail = [
+            manufacturer.clone(),
+            serial_number.as_ref().map(|sn| format!("SN {sn}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+        let label = match (&product, detail.is_empty()) {
+            (Some(product), false) => format!("{product} ({detail})"),
+            (Some(product), true) => product.clone(),
+            (None, false) => format!("{name} ({detail})"),
+            (None, true) => name.clone(),
+        };
+        Self {
+            name,
+            vid,
+            pid,
+            manufacturer,
+            product,
+            serial_number,
+            in_use: false,
+            label,
+        }
+    }
+    pub fn vid_pid(&self) -> Option<String> {
+        Some(format!("{:04x}:{:04x}", self.vid?, self.pid?))
+    }
+}
+
+// USBの情報を付加したシリアルポート一覧(in_use はここでは設定しない)
+pub fn get_serial_port_info() -> Vec<PortInfo> {
+    let usb_ports = serialport::available_ports().unwrap_or_default();
+    get_serial_port_list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| {
+            let usb = usb_ports
+                .iter()
+                .find(|info| info.port_name == name)
+                .and_then(|info| match &info.port_type {
+                    serialport::SerialPortType::UsbPort(usb) => Some(usb.clone()),
+                    _ => None,
+                });
+            PortInfo::new(name, usb)
+        })
+        .collect()
+}
+
+// USBシリアル変換器のシリアル番号
+pub fn usb_serial_number(port_name: &str) -> Option<String> {
+    serialport::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|info| info.port_name == port_name)
+        .and_then(|info| match info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => usb.serial_number,
+            _ => None,
+        })
+}
+
+// シリアル番号が一致するUSBシリアル変換器のポート名
+pub fn find_port_by_serial_number(serial_number: &str) -> Option<String> {
+    serialport::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|info| match &info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => {
+                usb.serial_number.as_deref() == Some(serial_number)
+            }
+            _ => false,
+        })
+        .map(|info| info.port_name)
+}
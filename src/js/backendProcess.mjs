@@ -13,26 +13,240 @@ class TauriBackEnd {
     set onseq_msg(callback) {
         window.__TAURI__.event.listen("sequencer-msg", callback);
     }
+    /**
+     * @description シリアルポートの抜き差し通知 (added: true/false)
+     */
+    set onport_change(callback) {
+        window.__TAURI__.event.listen("port-added", ({ payload }) =>
+            callback(payload, true),
+        );
+        window.__TAURI__.event.listen("port-removed", ({ payload }) =>
+            callback(payload, false),
+        );
+    }
+    set ondisconnected(callback) {
+        window.__TAURI__.event.listen("disconnected", callback);
+    }
+    set onreconnecting(callback) {
+        window.__TAURI__.event.listen("reconnecting", callback);
+    }
+    set onreconnected(callback) {
+        window.__TAURI__.event.listen("reconnected", callback);
+    }
+    /**
+     * @description 接続状態の変化通知 ({ state, port, message })
+     */
+    set onstatus_changed(callback) {
+        window.__TAURI__.event.listen("status-changed", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    get_connection_status() {
+        return invoke("get_connection_status");
+    }
+    set ondevice_printf(callback) {
+        window.__TAURI__.event.listen("device-printf", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    get_printf_log() {
+        return invoke("get_printf_log");
+    }
+    clear_printf_log() {
+        return invoke("clear_printf_log");
+    }
     file_open(path) {
         return invoke("open_file", { path });
     }
     send_file() {
         return invoke("send_midi_file");
     }
-    send_srec(fname) {
-        return invoke("send_srec_file", { fname });
+    /**
+     * @description 読み込み済みのMIDIファイルが更新されたら送り直す
+     */
+    set_midi_live_reload(enabled) {
+        return invoke("set_midi_live_reload", { enabled });
+    }
+    /**
+     * @description 開いた曲の範囲を切り出して送る
+     * range: { start, end } 位置は "80" / "80.3" (小節.拍) または "83.5s" / "1:23.5" (時間)、end は null なら最後まで
+     */
+    send_excerpt(range, loop_playback = false, loop_count = null) {
+        return invoke("send_midi_excerpt", {
+            range,
+            loopPlayback: loop_playback,
+            loopCount: loop_count,
+        });
+    }
+    /**
+     * @description 読み込み済みの曲を count 回(null なら止めるまで)繰り返す
+     */
+    start_loop(count = null) {
+        return invoke("start_loop", { count });
+    }
+    stop_loop() {
+        return invoke("stop_loop");
+    }
+    get_loop() {
+        return invoke("get_loop");
+    }
+    /**
+     * @description ループ再生の状態通知 ({ active, count, played, message })
+     */
+    set onloop_changed(callback) {
+        window.__TAURI__.event.listen("loop-changed", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description プレイリストの操作(変更は onplaylist_changed で通知される)
+     */
+    get_playlist() {
+        return invoke("get_playlist");
+    }
+    playlist_add(paths) {
+        return invoke("playlist_add", { paths });
+    }
+    playlist_remove(index) {
+        return invoke("playlist_remove", { index });
+    }
+    playlist_move(from, to) {
+        return invoke("playlist_move", { from, to });
+    }
+    playlist_shuffle() {
+        return invoke("playlist_shuffle");
+    }
+    playlist_clear() {
+        return invoke("playlist_clear");
+    }
+    /**
+     * @description index の曲から連続再生する(null なら現在の曲から)
+     */
+    playlist_play(index = null) {
+        return invoke("playlist_play", { index });
+    }
+    playlist_stop() {
+        return invoke("playlist_stop");
+    }
+    /**
+     * @description options: { repeat: "off" | "one" | "all", gap_ms }
+     */
+    set_playlist_options(options) {
+        return invoke("set_playlist_options", { options });
+    }
+    import_playlist(path) {
+        return invoke("import_playlist", { path });
+    }
+    export_playlist(path) {
+        return invoke("export_playlist", { path });
+    }
+    /**
+     * @description プレイリストの状態通知 ({ entries, current, next, playing, options, message })
+     */
+    set onplaylist_changed(callback) {
+        window.__TAURI__.event.listen("playlist-changed", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description 更新されたMIDIファイルの再読み込み通知 ({ path, sent, message })
+     */
+    set onmidi_reloaded(callback) {
+        window.__TAURI__.event.listen("midi-reloaded", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description SRECファイルを検証して概要と識別情報を返す(不正なら行番号付きでreject)
+     */
+    inspect_srec(path) {
+        return invoke("inspect_srec_file", { path });
+    }
+    /**
+     * @description 書き込みの進捗通知 ({ records_sent, total_records, bytes_sent, total_bytes, eta_ms })
+     */
+    set onflash_progress(callback) {
+        window.__TAURI__.event.listen("flash-progress", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description ELF/HEX/バイナリを変換したSRECを保存する
+     */
+    save_converted_srec(path, dest) {
+        return invoke("save_converted_srec", { path, dest });
+    }
+    /**
+     * @description force: バリアント違い/ダウングレードでも書き込む
+     */
+    send_srec(fname, force = false) {
+        return invoke("send_srec_file", { fname, force });
+    }
+    /**
+     * @description ローダの起動待ち→書き込み→Sequencer ready までを行う
+     */
+    flash_firmware(fname, force = false) {
+        return invoke("flash_firmware", { fname, force });
+    }
+    /**
+     * @description ファームウェアの更新を監視して書き込む
+     */
+    start_firmware_watch(path) {
+        return invoke("start_firmware_watch", { path });
+    }
+    stop_firmware_watch() {
+        return invoke("stop_firmware_watch");
+    }
+    /**
+     * @description 監視モードの状態通知 ({ path, stage, message })
+     */
+    set onfirmware_watch(callback) {
+        window.__TAURI__.event.listen("firmware-watch", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description ボードが起動時に報告したバージョン ({ variant, version })
+     */
+    set onboard_firmware(callback) {
+        window.__TAURI__.event.listen("board-firmware", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description 書き込みの進行段階の通知 ("wait-loader", "transfer", ...)
+     */
+    set onboot_stage(callback) {
+        window.__TAURI__.event.listen("boot-stage", ({ payload }) =>
+            callback(payload),
+        );
+    }
+    /**
+     * @description ログを1つのファイルにまとめて書き出す
+     */
+    export_logs(path) {
+        return invoke("export_logs", { path });
+    }
+    get_config() {
+        return invoke("get_config");
+    }
+    update_config(config) {
+        return invoke("update_config", { config });
     }
 }
 class SerialPort {
     /**
-     * @description This function does not return success or failure.
-     * @returns
+     * @description 接続が完了すると接続状態を返す(失敗時はreject)
+     * @returns {Promise<Object>}
      */
-    open(portName) {
-        return invoke("serialport_open", { portName });
+    open(portName, settings = null) {
+        return invoke("serialport_open", { portName, settings });
+    }
+    get_settings(portName) {
+        return invoke("get_serial_settings", { portName });
     }
     /**
-     * @description This function does not return success or failure.
+     * @description ポートが閉じられるまで待つ
      * @returns
      */
     close() {
@@ -41,5 +255,18 @@ class SerialPort {
     get_available_ports() {
         return invoke("get_available_serial_ports", {});
     }
+    /**
+     * @description MIQSボードが接続されたポート一覧を返す
+     * @returns {Promise<String[]>}
+     */
+    detect_boards() {
+        return invoke("detect_boards");
+    }
+    get_reconnect_policy() {
+        return invoke("get_reconnect_policy");
+    }
+    set_reconnect_policy(policy) {
+        return invoke("set_reconnect_policy", { policy });
+    }
 }
 export const BackEnd = new TauriBackEnd();
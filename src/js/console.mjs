@@ -2,8 +2,9 @@ const _log = console.log;
 const _info = console.info;
 const _warn = console.warn;
 const _error = console.error;
+let target;
 export default function console_override(id) {
-    const target = document.getElementById(id);
+    target = document.getElementById(id);
     console.log = (...args) => {
         target.value += `Log  : ${args}\n`;
         _log.apply(console, args);
@@ -21,3 +22,12 @@ export default function console_override(id) {
         _error.apply(console, args);
     };
 }
+/**
+ * @description デバイスのprintf出力を1行表示する
+ * @param {{timestamp: number, text: String}} line
+ */
+export function print_device(line) {
+    const time = new Date(line.timestamp).toLocaleTimeString();
+    target.value += `Dev  : [${time}] ${line.text}\n`;
+    target.scrollTop = target.scrollHeight;
+}
@@ -1,4 +1,4 @@
-const { open, message } = window.__TAURI__.dialog;
+const { open, save, message, confirm } = window.__TAURI__.dialog;
 /**
  *
  * @param {String} msg
@@ -27,4 +27,15 @@ export async function infoDialog(msg, title = "Info") {
     message(msg, { title, type: "info" });
 }
 
-export { open };
+/**
+ * @description OKなら true を返す
+ * @param {String} msg
+ * @param {String} title
+ * @returns {Promise<boolean>}
+ */
+export async function confirmDialog(msg, title = "Confirm") {
+    console.warn(msg);
+    return confirm(msg, { title, type: "warning" });
+}
+
+export { open, save };
@@ -1,5 +1,11 @@
-import console_override from "./js/console.mjs";
-import { open, warningDialog } from "./js/dialog.mjs";
+import console_override, { print_device } from "./js/console.mjs";
+import {
+  confirmDialog,
+  infoDialog,
+  open,
+  save,
+  warningDialog,
+} from "./js/dialog.mjs";
 import PeriodicTask from "./js/periodic.mjs";
 import PianoRoll from "./js/pianoroll.mjs";
 import PerformanceMonitor from "./js/performMonitor.mjs";
@@ -28,16 +34,32 @@ window.onload = () => {
     warningDialog(msg);
   };
   BackEnd.onmessage = ({ payload }) => {
-    console.log(payload);
-    if (payload?.id == 1) {
-      is_serial_port_open = true;
-      document.getElementById("setSerialPortButton").innerHTML = "Close";
-    } else if (payload?.id == 2) {
-      is_serial_port_open = false;
-      document.getElementById("setSerialPortButton").innerHTML = "Open";
-    }
     console.log(payload.msg);
   }
+  // デバイスのprintf出力(再読み込み時は保持分を表示)
+  BackEnd.ondevice_printf = printDeviceLine;
+  BackEnd.get_printf_log().then((lines) => lines.forEach(printDeviceLine));
+  BackEnd.onstatus_changed = updateConnectionStatus;
+  BackEnd.get_connection_status().then(updateConnectionStatus);
+  BackEnd.onport_change = (port, added) => {
+    console.log(
+      `Serial port ${added ? "added" : "removed"}: ${port.label} (${port.name})`,
+    );
+  };
+  BackEnd.ondisconnected = ({ payload }) => {
+    console.warn(`Serial port ${payload.port} disconnected: ${payload.reason}`);
+    document.getElementById("reconnect-status").innerHTML =
+      `Disconnected: ${payload.port}`;
+  };
+  BackEnd.onreconnecting = ({ payload }) => {
+    document.getElementById("reconnect-status").innerHTML =
+      `Reconnecting to ${payload.port} (attempt ${payload.attempt})`;
+  };
+  BackEnd.onreconnected = ({ payload }) => {
+    console.log(`Reconnected: ${payload}`);
+    document.getElementById("reconnect-status").innerHTML =
+      `Reconnected: ${payload}`;
+  };
   // 描画に関する初期化
   piano_roll = new PianoRoll("pianoRoll");
   performance_monitor = new PerformanceMonitor("currentPlayState");
@@ -69,23 +91,96 @@ window.onload = () => {
       ],
     });
     if (!selected) return;
-    // ファイルオープン/ファイル形式確認
-    BackEnd.file_open(selected)
-      .then(() => {
-        // receipt
-        const fname = selected.split(/\/|\\/).at(-1);
-        // 表示の変更
-        document.getElementById("fname-display").innerHTML = fname;
-        document.getElementById(
-          "midi-file-open-container",
-        ).dataset.tooltip = fname;
-        enableSendButton();
-      })
-      .catch((err) => {
-        // failed or reject
-        warningDialog(err);
-      });
+    openMidiFile(selected);
+  };
+  document.getElementById("recentMidiFiles").onchange = (e) => {
+    if (e.target.value) openMidiFile(e.target.value);
+  };
+  document.getElementById("midiLiveReloadInput").onchange = (e) => {
+    BackEnd.set_midi_live_reload(e.target.checked).catch(warningDialog);
+  };
+  BackEnd.onmidi_reloaded = ({ path, sent, message }) => {
+    const fname = path.split(/\/|\\/).at(-1);
+    const time = new Date().toLocaleTimeString();
+    let text;
+    if (message) {
+      text = `${fname}: ${message}`;
+      console.warn(text);
+    } else if (sent) {
+      text = `${fname}: new version sent (${time})`;
+      console.log(text);
+    } else {
+      text = `${fname}: reloaded, waiting for the board (${time})`;
+    }
+    document.getElementById("midi-reload-status").innerHTML = text;
   };
+  // 範囲を切り出して送信(ループの回数はループ再生と共通)
+  document.getElementById("excerptSendButton").onclick = () => {
+    const start = document.getElementById("excerptStartInput").value.trim();
+    const end = document.getElementById("excerptEndInput").value.trim();
+    if (!start) {
+      warningDialog("Start position is empty");
+      return;
+    }
+    const count = Number(document.getElementById("loopCountInput").value);
+    BackEnd.send_excerpt(
+      { start, end: end || null },
+      document.getElementById("excerptLoopInput").checked,
+      count > 0 ? count : null,
+    )
+      .then(() => console.log(`Sent excerpt: ${start} - ${end || "end"}`))
+      .catch(warningDialog);
+  };
+  // ループ再生
+  document.getElementById("loopStartButton").onclick = () => {
+    const count = Number(document.getElementById("loopCountInput").value);
+    BackEnd.start_loop(count > 0 ? count : null).catch(warningDialog);
+  };
+  document.getElementById("loopStopButton").onclick = () =>
+    BackEnd.stop_loop().catch(warningDialog);
+  BackEnd.onloop_changed = showLoop;
+  BackEnd.get_loop().then(showLoop);
+  // プレイリスト
+  const midiFilters = [
+    { name: "Midi", extensions: ["mid"] },
+    { name: "All", extensions: ["*"] },
+  ];
+  const playlistFilters = [{ name: "Playlist", extensions: ["m3u", "m3u8"] }];
+  document.getElementById("playlistAddButton").onclick = async () => {
+    const selected = await open({ multiple: true, filters: midiFilters });
+    if (!selected) return;
+    BackEnd.playlist_add([selected].flat()).catch(warningDialog);
+  };
+  document.getElementById("playlistImportButton").onclick = async () => {
+    const selected = await open({ multiple: false, filters: playlistFilters });
+    if (!selected) return;
+    BackEnd.import_playlist(selected).catch(warningDialog);
+  };
+  document.getElementById("playlistExportButton").onclick = async () => {
+    const dest = await save({ filters: playlistFilters });
+    if (!dest) return;
+    BackEnd.export_playlist(dest)
+      .then(() => console.log(`Saved playlist: ${dest}`))
+      .catch(warningDialog);
+  };
+  document.getElementById("playlistShuffleButton").onclick = () =>
+    BackEnd.playlist_shuffle().catch(warningDialog);
+  document.getElementById("playlistClearButton").onclick = () =>
+    BackEnd.playlist_clear().catch(warningDialog);
+  document.getElementById("playlistPlayButton").onclick = () =>
+    BackEnd.playlist_play().catch(warningDialog);
+  document.getElementById("playlistStopButton").onclick = () =>
+    BackEnd.playlist_stop().catch(warningDialog);
+  const savePlaylistOptions = () => {
+    BackEnd.set_playlist_options({
+      repeat: document.getElementById("playlistRepeatInput").value,
+      gap_ms: Number(document.getElementById("playlistGapInput").value),
+    }).catch(warningDialog);
+  };
+  document.getElementById("playlistRepeatInput").onchange = savePlaylistOptions;
+  document.getElementById("playlistGapInput").onchange = savePlaylistOptions;
+  BackEnd.onplaylist_changed = showPlaylist;
+  BackEnd.get_playlist().then(showPlaylist);
   document.getElementById("swichPlayerBtn").onclick = document.getElementById(
     "swichMainBtn",
   ).onclick = togglePlayer;
@@ -95,8 +190,7 @@ window.onload = () => {
       const serialPortInput =
         document.getElementById("serialPortInput").value;
       if (serialPortInput) {
-        // Note: This function does not return success or failure.
-        BackEnd.serialport.open(serialPortInput).catch((err) => {
+        BackEnd.serialport.open(serialPortInput, readSerialSettings()).catch((err) => {
           warningDialog(err);
         });
         // console.log(`Success Open Serial port : ${serialPortInput}`)
@@ -110,6 +204,26 @@ window.onload = () => {
     }
 
   };
+  // MIQSボードが接続されたポートを自動選択
+  document.getElementById("detectBoardButton").onclick = async () => {
+    BackEnd.serialport
+      .detect_boards()
+      .then((ports) => {
+        if (ports.length === 0) {
+          warningDialog("MIQS board not found.");
+          return;
+        }
+        document.getElementById("serialPortInput").value = ports[0];
+        console.log(`Detected board: ${ports.join(", ")}`);
+      })
+      .catch((err) => {
+        warningDialog(err);
+      });
+  };
+  // ポートを選んだら前回の通信設定を表示
+  document.getElementById("serialPortInput").onchange = async (e) => {
+    BackEnd.serialport.get_settings(e.target.value).then(writeSerialSettings);
+  };
   // 利用可能なシリアルポートのサジェストを作成
   document.getElementById("serialPortInput").onfocus = async () => {
     BackEnd.serialport.get_available_ports().then((ports) => {
@@ -118,7 +232,8 @@ window.onload = () => {
         const fragment = document.createDocumentFragment();
         for (const port of ports) {
           const item = document.createElement("option");
-          item.value = port;
+          item.value = port.name;
+          item.label = port.in_use ? `${port.label} [in use]` : port.label;
           fragment.appendChild(item);
         }
         datalist.innerHTML = null;
@@ -126,6 +241,36 @@ window.onload = () => {
       }
     });
   };
+  // 自動再接続の設定
+  BackEnd.serialport.get_reconnect_policy().then((policy) => {
+    document.getElementById("reconnectEnabled").checked = policy.enabled;
+    document.getElementById("reconnectMaxAttempts").value =
+      policy.max_attempts;
+    const update = () => {
+      policy.enabled = document.getElementById("reconnectEnabled").checked;
+      policy.max_attempts =
+        Number(document.getElementById("reconnectMaxAttempts").value) || 0;
+      BackEnd.serialport.set_reconnect_policy(policy).catch((err) => {
+        warningDialog(err);
+      });
+    };
+    document.getElementById("reconnectEnabled").onchange = update;
+    document.getElementById("reconnectMaxAttempts").onchange = update;
+  });
+  document.getElementById("clearDeviceLogButton").onclick = async () => {
+    BackEnd.clear_printf_log().catch((err) => warningDialog(err));
+  };
+  // 不具合報告用のログ書き出し
+  document.getElementById("exportLogButton").onclick = async () => {
+    const path = await save({
+      defaultPath: "miqs_log.txt",
+      filters: [{ name: "Log", extensions: ["txt", "log"] }],
+    });
+    if (!path) return;
+    BackEnd.export_logs(path)
+      .then(() => infoDialog(`Logs exported to ${path}`))
+      .catch((err) => warningDialog(err));
+  };
   // srec送信Window
   document.getElementById("srec-panel-close").onclick = () => {
     document.getElementById("config-window").classList.toggle("hide");
@@ -149,8 +294,8 @@ window.onload = () => {
       multiple: false,
       filters: [
         {
-          name: "Srec",
-          extensions: ["srec"],
+          name: "Firmware",
+          extensions: ["srec", "s19", "s28", "s37", "mot", "elf", "hex", "bin"],
         },
         {
           name: "All",
@@ -159,20 +304,186 @@ window.onload = () => {
       ],
     });
     if (!selected) return;
-    // receipt
+    selectSrecFile(selected);
+  };
+  document.getElementById("recentSrecFiles").onchange = (e) => {
+    if (e.target.value) selectSrecFile(e.target.value);
+  };
+  // srec fileの選択(表示の変更)
+  function selectSrecFile(selected) {
     const fname = selected.split(/\/|\\/).at(-1);
-    // 表示の変更
     document.getElementById("srecFname-display").innerHTML = fname;
     document.getElementById(
       "srec-file-file-open-container",
     ).dataset.tooltip = fname;
     srec_fname = selected;
+    BackEnd.inspect_srec(selected)
+      .then(showSrecSummary)
+      .catch((err) => {
+        document.getElementById("srec-summary").innerHTML = `Error: ${err}`;
+      });
+  }
+  // 前回の設定を復元
+  BackEnd.get_config().then((config) => {
+    updateRecentFiles(config);
+    document.getElementById("midiLiveReloadInput").checked =
+      config.midi_live_reload;
+    if (config.last_port) {
+      document.getElementById("serialPortInput").value = config.last_port;
+      BackEnd.serialport.get_settings(config.last_port).then(writeSerialSettings);
+    }
+    if (config.last_srec_file) selectSrecFile(config.last_srec_file);
+    document.getElementById("flashModeInput").value = config.flash_options.mode;
+    document.getElementById("lineDelayInput").value =
+      config.flash_options.line_delay_ms;
+    document.getElementById("resetLineInput").value =
+      config.flash_options.reset;
+    const convert = config.convert_options;
+    document.getElementById("baseAddressInput").value =
+      convert.base_address === null
+        ? ""
+        : "0x" + convert.base_address.toString(16).toUpperCase();
+    document.getElementById("recordSizeInput").value = convert.record_size;
+    document.getElementById("reuploadMidiInput").checked =
+      config.firmware_watch.reupload_midi;
+    document.getElementById("addressWidthInput").value = convert.address_width;
+  });
+  // 変換方法の変更を設定に保存し、選択中のファイルを検証し直す
+  const saveConvertOptions = async () => {
+    const config = await BackEnd.get_config();
+    const base = document.getElementById("baseAddressInput").value.trim();
+    config.convert_options.base_address = base === "" ? null : Number(base);
+    config.convert_options.record_size = Number(
+      document.getElementById("recordSizeInput").value,
+    );
+    config.convert_options.address_width =
+      document.getElementById("addressWidthInput").value;
+    if (Number.isNaN(config.convert_options.base_address)) {
+      warningDialog(`Invalid base address: ${base}`);
+      return;
+    }
+    await BackEnd.update_config(config).catch(warningDialog);
+    if (srec_fname) selectSrecFile(srec_fname);
+  };
+  document.getElementById("baseAddressInput").onchange = saveConvertOptions;
+  document.getElementById("recordSizeInput").onchange = saveConvertOptions;
+  document.getElementById("addressWidthInput").onchange = saveConvertOptions;
+  document.getElementById("srec-save-button").onclick = async () => {
+    if (!srec_fname) return;
+    const dest = await save({
+      defaultPath: srec_fname.replace(/\.[^./\\]*$/, "") + ".srec",
+      filters: [{ name: "Srec", extensions: ["srec"] }],
+    });
+    if (!dest) return;
+    BackEnd.save_converted_srec(srec_fname, dest)
+      .then(() => infoDialog(`Saved ${dest}`))
+      .catch(warningDialog);
+  };
+  // 書き込み方式の変更を設定に保存
+  const saveFlashOptions = async () => {
+    const config = await BackEnd.get_config();
+    config.flash_options.mode = document.getElementById("flashModeInput").value;
+    config.flash_options.line_delay_ms = Number(
+      document.getElementById("lineDelayInput").value,
+    );
+    config.flash_options.reset = document.getElementById("resetLineInput").value;
+    BackEnd.update_config(config).catch(warningDialog);
+  };
+  document.getElementById("flashModeInput").onchange = saveFlashOptions;
+  document.getElementById("lineDelayInput").onchange = saveFlashOptions;
+  document.getElementById("resetLineInput").onchange = saveFlashOptions;
+  const BOOT_STAGES = {
+    reset: "Reset Board",
+    "wait-loader": "Wait for Loader",
+    transfer: "Transfer SREC",
+    "wait-load": "Wait for Load Result",
+    "wait-ready": "Wait for Sequencer",
+    done: "Done",
+  };
+  BackEnd.onboot_stage = (stage) => {
+    document.getElementById("boot-stage").innerHTML = BOOT_STAGES[stage];
+  };
+  BackEnd.onflash_progress = (progress) => {
+    const eta = ((progress.eta_ms ?? 0) / 1000).toFixed(1);
+    document.getElementById("flash-progress").innerHTML =
+      `${progress.records_sent}/${progress.total_records} records, ` +
+      `${progress.bytes_sent}/${progress.total_bytes} bytes, ETA ${eta}s`;
+  };
+  // ローダの起動からシーケンサの起動確認までを一度に行う
+  // バリアント違い/ダウングレードは確認してから書き込む
+  async function confirmFirmware(fname) {
+    try {
+      const report = await BackEnd.inspect_srec(fname);
+      showSrecSummary(report);
+      if (report.warnings.length === 0) return true;
+      return await confirmDialog(
+        `${report.warnings.join("\n")}\n\nFlash anyway?`,
+        "Firmware check",
+      );
+    } catch (err) {
+      warningDialog(err);
+      return false;
+    }
+  }
+  // 監視モード(ファームウェアの再ビルドを検知して書き込む)
+  document.getElementById("watchFirmwareInput").onchange = async (e) => {
+    if (!e.target.checked) {
+      BackEnd.stop_firmware_watch();
+      return;
+    }
+    const config = await BackEnd.get_config();
+    config.firmware_watch.reupload_midi =
+      document.getElementById("reuploadMidiInput").checked;
+    await BackEnd.update_config(config).catch(warningDialog);
+    BackEnd.start_firmware_watch(srec_fname).catch((err) => {
+      e.target.checked = false;
+      warningDialog(err);
+    });
+  };
+  document.getElementById("reuploadMidiInput").onchange = async (e) => {
+    const config = await BackEnd.get_config();
+    config.firmware_watch.reupload_midi = e.target.checked;
+    await BackEnd.update_config(config).catch(warningDialog);
+    // 監視中なら新しい設定で監視し直す
+    if (document.getElementById("watchFirmwareInput").checked) {
+      BackEnd.start_firmware_watch(srec_fname).catch(warningDialog);
+    }
+  };
+  BackEnd.onfirmware_watch = ({ path, stage, message }) => {
+    const fname = path.split(/\/|\\/).at(-1);
+    document.getElementById("firmware-watch-status").innerHTML = message
+      ? `${stage}: ${message}`
+      : `${stage} (${fname})`;
+    if (stage === "done") {
+      BackEnd.inspect_srec(path).then(showSrecSummary).catch(() => {});
+    }
+  };
+  BackEnd.onboard_firmware = (version) => {
+    console.log(`Board firmware: ${formatVersion(version)}`);
+  };
+  document.getElementById("srec-boot-button").onclick = async () => {
+    if (!(await confirmFirmware(srec_fname))) return;
+    BackEnd.flash_firmware(srec_fname, true)
+      .then((summary) => {
+        showSrecSummary(summary);
+        BackEnd.get_config().then(updateRecentFiles);
+        infoDialog("Sequencer ready");
+      })
+      .catch((err) => {
+        warningDialog(err);
+      });
   };
   document.getElementById("srec-send-button").onclick = async () => {
     console.log(srec_fname);
-    BackEnd.send_srec(srec_fname).catch((err) => {
-      console.error(err);
-    });
+    if (!(await confirmFirmware(srec_fname))) return;
+    BackEnd.send_srec(srec_fname, true)
+      .then((summary) => {
+        showSrecSummary(summary);
+        BackEnd.get_config().then(updateRecentFiles);
+      })
+      .catch((err) => {
+        warningDialog(err);
+      });
   };
 };
 
@@ -194,15 +505,212 @@ function togglePlayer() {
   }
 }
 
+// ファイルオープン/ファイル形式確認
+function openMidiFile(selected) {
+  BackEnd.file_open(selected)
+    .then(() => {
+      // receipt
+      const fname = selected.split(/\/|\\/).at(-1);
+      // 表示の変更
+      document.getElementById("fname-display").innerHTML = fname;
+      document.getElementById(
+        "midi-file-open-container",
+      ).dataset.tooltip = fname;
+      enableSendButton();
+      BackEnd.get_config().then(updateRecentFiles);
+    })
+    .catch((err) => {
+      // failed or reject
+      warningDialog(err);
+    });
+}
+
+// ループ再生の回数表示
+function showLoop({ active, count, played, message }) {
+  let status = "";
+  if (message) {
+    status = `Loop stopped: ${message}`;
+  } else if (active) {
+    status = `Loop ${played}/${count ?? "∞"}`;
+  } else if (played > 0) {
+    status = `Loop finished (${played})`;
+  }
+  document.getElementById("loop-status").textContent = status;
+  document.getElementById("loopStopButton").disabled = !active;
+}
+
+// プレイリストの表示(曲名クリックでその曲から再生)
+function showPlaylist({ entries, current, next, playing, options, message }) {
+  const fname = (path) => path.split(/\/|\\/).at(-1);
+  const list = document.getElementById("playlistEntries");
+  const fragment = document.createDocumentFragment();
+  entries.forEach((path, index) => {
+    const item = document.createElement("li");
+    const title = document.createElement("span");
+    title.textContent = fname(path);
+    title.title = path;
+    if (index === current) title.classList.add("current");
+    title.onclick = () => BackEnd.playlist_play(index).catch(warningDialog);
+    item.appendChild(title);
+    const buttons = [
+      ["↑", () => BackEnd.playlist_move(index, index - 1), index > 0],
+      ["↓", () => BackEnd.playlist_move(index, index + 1), index + 1 < entries.length],
+      ["×", () => BackEnd.playlist_remove(index), true],
+    ];
+    for (const [label, action, enabled] of buttons) {
+      const button = document.createElement("button");
+      button.textContent = label;
+      button.disabled = !enabled;
+      button.onclick = () => action().catch(warningDialog);
+      item.appendChild(button);
+    }
+    fragment.appendChild(item);
+  });
+  list.replaceChildren(fragment);
+  document.getElementById("playlistRepeatInput").value = options.repeat;
+  document.getElementById("playlistGapInput").value = options.gap_ms;
+  let status = "";
+  if (playing && current !== null) {
+    status = `Now: ${fname(entries[current])}`;
+    status += next === null ? " (last)" : ` / Next: ${fname(entries[next])}`;
+  }
+  if (message) {
+    status = message;
+    console.warn(`Playlist: ${message}`);
+  }
+  document.getElementById("playlist-status").textContent = status;
+}
+
+// 最近使ったファイルの一覧を更新
+function updateRecentFiles(config) {
+  const lists = [
+    ["recentMidiFiles", config.recent_midi_files],
+    ["recentSrecFiles", config.recent_srec_files],
+  ];
+  for (const [id, files] of lists) {
+    const select = document.getElementById(id);
+    const fragment = document.createDocumentFragment();
+    const placeholder = document.createElement("option");
+    placeholder.value = "";
+    placeholder.textContent = "Recent files";
+    fragment.appendChild(placeholder);
+    for (const file of files) {
+      const item = document.createElement("option");
+      item.value = file;
+      item.textContent = file.split(/\/|\\/).at(-1);
+      fragment.appendChild(item);
+    }
+    select.innerHTML = null;
+    select.appendChild(fragment);
+  }
+}
+
+// 通信設定の入力欄を読み出す
+function readSerialSettings() {
+  return {
+    baud_rate: Number(document.getElementById("baudRateInput").value),
+    char_size: Number(document.getElementById("dataBitsInput").value),
+    parity: document.getElementById("parityInput").value,
+    stop_bits: Number(document.getElementById("stopBitsInput").value),
+    flow_control: document.getElementById("flowControlInput").value,
+    dtr: null,
+    rts: null,
+    printf_encoding: document.getElementById("printfEncodingInput").value,
+  };
+}
+
+function writeSerialSettings(settings) {
+  document.getElementById("baudRateInput").value = settings.baud_rate;
+  document.getElementById("dataBitsInput").value = settings.char_size;
+  document.getElementById("parityInput").value = settings.parity;
+  document.getElementById("stopBitsInput").value = settings.stop_bits;
+  document.getElementById("flowControlInput").value = settings.flow_control;
+  document.getElementById("printfEncodingInput").value =
+    settings.printf_encoding;
+}
+
+function formatVersion(version) {
+  return version.variant
+    ? `${version.variant} v${version.version}`
+    : `v${version.version}`;
+}
+
+// SRECファイルの概要を表示
+function showSrecSummary(summary) {
+  const hex = (v) => "0x" + v.toString(16).toUpperCase().padStart(8, "0");
+  const lines = [];
+  if (summary.header) lines.push(`header: ${summary.header}`);
+  lines.push(
+    `${summary.record_count} records, ${summary.data_bytes} bytes (${summary.address_width}-bit address)`,
+  );
+  for (const range of summary.ranges) {
+    lines.push(`  ${hex(range.start)}-${hex(range.end - 1)}`);
+  }
+  lines.push(
+    `entry point: ${summary.entry_point === null ? "none" : hex(summary.entry_point)}`,
+  );
+  // 識別情報と実行中のバージョン
+  const info = summary.info;
+  lines.push(
+    `version: ${info.version ? formatVersion(info.version) : "unknown"}`,
+    `size: ${info.image_size} bytes, crc32: ${info.crc32.toString(16).toUpperCase().padStart(8, "0")}`,
+  );
+  if (summary.running) {
+    lines.push(`running: ${formatVersion(summary.running)}`);
+  }
+  for (const warning of summary.warnings) {
+    lines.push(`warning: ${warning}`);
+  }
+  document.getElementById("srec-summary").innerHTML = lines.join("\n");
+}
+
+// デバイスのprintf出力をレベル/モジュールで絞り込んで表示
+const DEVICE_LEVELS = ["error", "warn", "info", "debug", "trace"];
+function printDeviceLine(line) {
+  const max_level = document.getElementById("deviceLevelFilter").value;
+  // タグのない行は info として扱う
+  const level = line.level ?? "info";
+  if (
+    max_level &&
+    DEVICE_LEVELS.indexOf(level) > DEVICE_LEVELS.indexOf(max_level)
+  ) {
+    return;
+  }
+  const modules = document
+    .getElementById("deviceModuleFilter")
+    .value.split(",")
+    .map((m) => m.trim())
+    .filter((m) => m);
+  if (modules.length > 0 && !modules.includes(line.module)) {
+    return;
+  }
+  print_device(line);
+}
+
+// 接続状態の表示を更新
+function updateConnectionStatus(status) {
+  // 接続中(再接続待ちを含む)は Close で中止できる
+  is_serial_port_open =
+    status.port !== null && status.state !== "disconnected";
+  document.getElementById("setSerialPortButton").innerHTML =
+    is_serial_port_open ? "Close" : "Open";
+  const label = status.port ? `${status.state} (${status.port})` : status.state;
+  document.getElementById("connection-status").innerHTML = status.message
+    ? `${label}: ${status.message}`
+    : label;
+}
+
 // 送信ボタンを表示する関数
 function enableSendButton() {
   const sendButton = document.getElementById("sendButton");
   // disabled を false にする
   sendButton.disabled = false;
+  document.getElementById("excerptSendButton").disabled = false;
 }
 
 // 送信ボタンを無効にする関数
 function disableSendButton() {
   const sendButton = document.getElementById("sendButton");
   sendButton.disabled = true;
+  document.getElementById("excerptSendButton").disabled = true;
 }